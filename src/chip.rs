@@ -1,21 +1,34 @@
-#[allow(unused_imports)]
-use minifb::{Key, Window, WindowOptions, Scale};
-use rand::Rng;
-
 use std::num::Wrapping;
 
+use crate::clip::Clip;
 use crate::decode::{self, Decoded};
 use crate::font;
+use crate::frontend::{Frontend, Hotkey, WindowSettings};
 use crate::hex;
+use crate::history::Undo;
+use crate::image::Image;
+use crate::interrupt;
+use crate::movie::Movie;
+use crate::palette::Palette;
+use crate::rewind::Rewind;
+use crate::rng::{Algorithm, Rng, VipPage};
+use crate::state::State;
 use crate::timer::Timer;
+use crate::trace::{Record, Registers, Tracer};
 use crate::types::*;
 
 const MEMORY_SIZE: usize = 4096;
 const FONT_BYTE_COUNT: Address = 5;
 const FONT_MEMORY_OFFSET: usize = 0;
-const PROGRAM_MEMORY_OFFSET: usize = 512;
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+pub const PROGRAM_MEMORY_OFFSET: usize = 512;
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+const STEPS_PER_FRAME: u64 = 12;
+const FRAMES_PER_SECOND: usize = 60;
+const REWIND_SECONDS: usize = 30;
+pub const IMAGE_SCALE: usize = 8;
 
 pub struct Chip {
     pc: Address,
@@ -24,13 +37,25 @@ pub struct Chip {
     memory: Vec<Byte>,
     stack: Vec<Address>,
     delay: Timer,
-    display: Vec<u32>,
-    window: Window,
+    sound: Timer,
+    // SUPER-CHIP's 128x64 mode, switched with 00FF and back with 00FE.
+    hires: bool,
+    display: Vec<u8>,
+    palette: Palette,
+    frontend: Frontend,
+    cycles: u64,
+    tracer: Option<Tracer>,
+    keypad: u16,
+    rng: Rng,
+    vip_page: Option<VipPage>,
+    rom_path: String,
+    movie: Option<Movie>,
+    clip: Option<Clip>,
 }
 
 // Public interface.
 impl Chip {
-    pub fn new() -> Self {
+    pub fn new(frontend: Frontend) -> Self {
         Self {
             pc: PROGRAM_MEMORY_OFFSET as Address,
             i: 0,
@@ -38,28 +63,24 @@ impl Chip {
             memory: vec![0; MEMORY_SIZE],
             stack: Vec::new(),
             delay: Timer::new(),
+            sound: Timer::new(),
+            hires: false,
             display: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
-            window: Self::new_window(),
+            palette: Palette::default(),
+            frontend,
+            cycles: 0,
+            tracer: None,
+            keypad: 0,
+            rng: Rng::from_entropy(),
+            vip_page: None,
+            rom_path: "nn".to_string(),
+            movie: None,
+            clip: None,
         }
     }
 
-    fn new_window() -> Window {
-        let mut window_options = WindowOptions::default();
-        window_options.scale = Scale::X8;
-
-        let mut window = Window::new(
-            "CHIP 8",
-            DISPLAY_WIDTH,
-            DISPLAY_HEIGHT,
-            window_options,
-        )
-        .unwrap_or_else(|e| {
-            panic!("{}", e);
-        });
-
-        window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
-
-        window
+    pub fn window(settings: WindowSettings) -> Frontend {
+        Frontend::window(DISPLAY_WIDTH, DISPLAY_HEIGHT, settings)
     }
 
     pub fn load_font(&mut self) {
@@ -75,21 +96,477 @@ impl Chip {
         }
     }
 
-    pub fn run(&mut self) {
+    // Runs until the window is closed, a break is requested or, if given,
+    // that many frames have run, and returns whether a break was requested.
+    pub fn run(&mut self, frames: Option<u64>) -> bool {
         self.load_font();
-        while self.window.is_open() {
+        let mut rewind = Rewind::new(&self.memory, REWIND_SECONDS * FRAMES_PER_SECOND);
+        let mut run = 0;
+
+        while self.frontend.is_open() && frames.is_none_or(|frames| run < frames) {
+            if self.break_requested() {
+                self.frontend.suspend();
+                return true;
+            }
+            if self.handle_hotkeys() {
+                rewind.clear();
+            }
+
+            // Holding the rewind key steps back a frame at a time.
+            if self.frontend.held(Hotkey::Rewind) && self.movie.is_none() {
+                match rewind.pop() {
+                    Some(state) => self.restore(&state).unwrap(),
+                    None => self.draw(),
+                }
+                continue;
+            }
+
+            let playing = matches!(self.movie, Some(Movie::Playing { .. }));
+            self.run_frame();
+            rewind.push(self.state());
+            run += 1;
+
+            // With no window to go on in, a headless run ends with its movie.
+            if playing && self.movie.is_none() && self.frontend.is_headless() {
+                break;
+            }
+        }
+        false
+    }
+
+    // Steps up to the end of the current frame.
+    fn run_frame(&mut self) {
+        self.step();
+        while !self.cycles.is_multiple_of(STEPS_PER_FRAME) {
             self.step();
         }
     }
 
+    // Handles the screenshot and capture keys, and saves or loads state on
+    // the save and load keys, returning whether a state was loaded.
+    pub fn handle_hotkeys(&mut self) -> bool {
+        if self.frontend.pressed(Hotkey::Screenshot) {
+            let path = self.screenshot_path();
+            match self.screenshot().scaled(IMAGE_SCALE).save(&path) {
+                Ok(()) => println!("Saved screenshot to {path}"),
+                Err(err) => println!("{err}"),
+            }
+        }
+        if self.frontend.pressed(Hotkey::Capture) {
+            self.toggle_capture();
+        }
+
+        // Jumping about in time would spoil a movie.
+        if self.movie.is_some() {
+            return false;
+        }
+
+        if self.frontend.pressed(Hotkey::Save) {
+            let path = self.state_path();
+            match self.state().save(&path) {
+                Ok(()) => println!("Saved state to {path}"),
+                Err(err) => println!("{err}"),
+            }
+        }
+        if self.frontend.pressed(Hotkey::Load) {
+            let path = self.state_path();
+            match State::load(&path).and_then(|state| self.restore(&state)) {
+                Ok(()) => {
+                    println!("Loaded state from {path}");
+                    return true;
+                },
+                Err(err) => println!("{err}"),
+            }
+        }
+        false
+    }
+
+    // Files the chip writes are named after the ROM.
+    pub fn set_rom_path(&mut self, path: &str) {
+        self.rom_path = path.to_string();
+    }
+
+    pub fn state_path(&self) -> String {
+        format!("{}.state", self.rom_path)
+    }
+
+    pub fn screenshot_path(&self) -> String {
+        self.numbered_path("png")
+    }
+
+    // The first of rom_path-1.extension, rom_path-2.extension, ... not yet taken.
+    fn numbered_path(&self, extension: &str) -> String {
+        (1 ..)
+            .map(|n| format!("{}-{n}.{extension}", self.rom_path))
+            .find(|path| !std::path::Path::new(path).exists())
+            .unwrap()
+    }
+
+    pub fn screenshot(&self) -> Image {
+        let (width, height) = self.display_size();
+        Image::new(&self.palette.colours(&self.display), width, height)
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    // Starts capturing a clip to rom_path-n.gif, or finishes the one running.
+    fn toggle_capture(&mut self) {
+        match self.clip.take() {
+            Some(mut clip) => match clip.finish() {
+                Ok(()) => println!("Saved clip to {}", clip.path()),
+                Err(err) => println!("{err}"),
+            },
+            None => {
+                let path = self.numbered_path("gif");
+                match Clip::create(&path, DISPLAY_WIDTH, DISPLAY_HEIGHT, IMAGE_SCALE) {
+                    Ok(clip) => {
+                        println!("Capturing to {path}");
+                        self.clip = Some(clip);
+                    },
+                    Err(err) => println!("{err}"),
+                }
+            },
+        }
+    }
+
+    pub fn set_clip(&mut self, clip: Clip) {
+        self.clip = Some(clip);
+    }
+
+    // Checks for the break key in the window or SIGINT in the terminal.
+    pub fn break_requested(&mut self) -> bool {
+        interrupt::take() || self.frontend.pressed(Hotkey::Break)
+    }
+
+    // Called when the machine stops, so that a terminal frontend gives the
+    // terminal back.
+    pub fn suspend(&mut self) {
+        self.frontend.suspend();
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
     pub fn step(&mut self) {
+        let pc = self.pc;
+        let before = self.registers();
         let fetched = self.fetch();
         self.pc += 2;
 
         let decoded = decode::decode(fetched);
         self.exec(decoded);
+        self.cycles += 1;
+
+        let after = self.registers();
+
+        if let Some(tracer) = &mut self.tracer {
+            let record = Record {
+                cycle: self.cycles,
+                pc,
+                opcode: fetched,
+                before,
+                after,
+            };
+            if let Err(err) = tracer.trace(&record) {
+                println!("Trace stopped: {err}");
+                self.tracer = None;
+            }
+        }
+
+        if self.cycles.is_multiple_of(STEPS_PER_FRAME) {
+            self.end_frame();
+        }
+    }
+
+    // Ticks the timers, updates the window and keys, and captures the frame
+    // for any clip. The window limits updates to 60 a second, which paces
+    // the whole machine.
+    fn end_frame(&mut self) {
+        self.delay.tick();
+        self.sound.tick();
+        self.rng.tick();
+        self.draw();
+        self.keypad = self.sample_keypad();
+
+        let image = self.clip.is_some().then(|| self.screenshot());
+        if let (Some(clip), Some(image)) = (&mut self.clip, image) {
+            if let Err(err) = clip.frame(image) {
+                println!("Capture stopped: {err}");
+                self.clip = None;
+            }
+        }
+    }
+
+    // Keys are read once a frame, from the window or a movie being played.
+    fn sample_keypad(&mut self) -> u16 {
+        let live = self.frontend.keypad();
+
+        match self.movie.as_mut().map(|movie| movie.frame(live)) {
+            Some(Some(keypad)) => keypad,
+            Some(None) => {
+                if let Some(Movie::Playing { .. }) = self.movie {
+                    println!("Movie finished");
+                }
+                self.movie = None;
+                live
+            },
+            None => live,
+        }
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) -> Result<(), String> {
+        self.check_rng(rng.algorithm())?;
+        self.rng = rng;
+        Ok(())
+    }
+
+    pub fn set_vip_page(&mut self, page: VipPage) {
+        self.vip_page = Some(page);
+    }
+
+    fn check_rng(&self, algorithm: Algorithm) -> Result<(), String> {
+        match (algorithm, self.vip_page) {
+            (Algorithm::Vip, None) => Err("the vip random number generator needs --vip-page".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_movie(&mut self, movie: Movie) {
+        self.movie = Some(movie);
+    }
 
-        //self.draw();
+    fn registers(&self) -> Registers {
+        Registers { v: self.v, i: self.i }
+    }
+}
+
+// State inspection.
+impl Chip {
+    pub fn is_open(&self) -> bool {
+        self.frontend.is_open()
+    }
+
+    pub fn pc(&self) -> Address {
+        self.pc
+    }
+
+    pub fn i(&self) -> Address {
+        self.i
+    }
+
+    pub fn v(&self, x: Register) -> Byte {
+        self.v[x]
+    }
+
+    pub fn next_instruction(&self) -> Instruction {
+        self.fetch()
+    }
+
+    pub fn display_size(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.display[y * self.display_size().0 + x] != 0
+    }
+
+    pub fn memory(&self) -> &[Byte] {
+        &self.memory
+    }
+
+    pub fn stack(&self) -> &[Address] {
+        &self.stack
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay.get()
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound.get()
+    }
+}
+
+// State modification.
+impl Chip {
+    pub fn set_pc(&mut self, addr: Address) {
+        self.pc = addr;
+    }
+
+    pub fn set_i(&mut self, addr: Address) {
+        self.i = addr;
+    }
+
+    pub fn set_v(&mut self, x: Register, byte: Byte) {
+        self.v[x] = byte;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay.set(value);
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound.set(value);
+    }
+
+    pub fn write_memory(&mut self, addr: usize, bytes: &[Byte]) {
+        self.memory[addr .. addr + bytes.len()].copy_from_slice(bytes);
+    }
+
+    pub fn fill_memory(&mut self, addr: usize, len: usize, byte: Byte) {
+        self.memory[addr .. addr + len].fill(byte);
+    }
+
+    pub fn push_stack(&mut self, addr: Address) {
+        self.stack.push(addr);
+    }
+
+    pub fn pop_stack(&mut self) -> Option<Address> {
+        self.stack.pop()
+    }
+}
+
+// Save states.
+impl Chip {
+    pub fn state(&self) -> State {
+        State {
+            pc: self.pc,
+            i: self.i,
+            v: self.v,
+            stack: self.stack.clone(),
+            delay: self.delay.get(),
+            sound: self.sound.get(),
+            keypad: self.keypad,
+            width: self.display_size().0,
+            height: self.display_size().1,
+            display: self.display.iter().map(|pixel| *pixel != 0).collect(),
+            rng: self.rng.state(),
+            rng_algorithm: self.rng.algorithm(),
+            cycles: self.cycles,
+            memory: self.memory.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: &State) -> Result<(), String> {
+        let hires = match (state.width, state.height) {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT) => false,
+            (HIRES_WIDTH, HIRES_HEIGHT) => true,
+            _ => return Err(format!("unsupported display size: {}x{}", state.width, state.height)),
+        };
+        if state.memory.len() != MEMORY_SIZE {
+            return Err(format!("unsupported memory size: {}", state.memory.len()));
+        }
+        self.check_rng(state.rng_algorithm)?;
+
+        self.pc = state.pc;
+        self.i = state.i;
+        self.v = state.v;
+        self.stack.clone_from(&state.stack);
+        self.delay.set(state.delay);
+        self.sound.set(state.sound);
+        self.keypad = state.keypad;
+        self.hires = hires;
+        self.display = state.display.iter().map(|on| *on as u8).collect();
+        self.rng.restore(state.rng_algorithm, state.rng);
+        self.cycles = state.cycles;
+        self.memory.clone_from(&state.memory);
+
+        self.draw();
+        Ok(())
+    }
+}
+
+// Reverse execution.
+impl Chip {
+    pub fn step_undoable(&mut self) -> Undo {
+        let i = self.i as usize;
+        let decoded = decode::decode(self.fetch());
+
+        let written = match decoded {
+            Decoded::Store(x) => i .. i + x + 1,
+            Decoded::Decimal(_) => i .. i + 3,
+            _ => i .. i,
+        };
+        let written = written.start.min(MEMORY_SIZE) .. written.end.min(MEMORY_SIZE);
+
+        let display = match decoded {
+            Decoded::Draw(..) | Decoded::ClearScreen | Decoded::LowRes | Decoded::HighRes => Some(self.display.clone()),
+            _ => None,
+        };
+
+        let mut undo = Undo {
+            pc: self.pc,
+            i: self.i,
+            v: self.v,
+            stack: self.stack.clone(),
+            delay: self.delay.get(),
+            sound: self.sound.get(),
+            memory: (written.start, self.memory[written].to_vec()),
+            hires: self.hires,
+            display: Vec::new(),
+            keypad: self.keypad,
+            rng: self.rng.state(),
+            movie: self.movie.as_ref().map(Movie::position),
+        };
+
+        self.step();
+
+        // A new resolution keeps every old pixel.
+        if let Some(before) = display {
+            let resized = before.len() != self.display.len();
+            undo.display = before
+                .into_iter()
+                .enumerate()
+                .filter(|(n, pixel)| resized || self.display[*n] != *pixel)
+                .collect();
+        }
+
+        undo
+    }
+
+    pub fn undo(&mut self, undo: &Undo) {
+        self.pc = undo.pc;
+        self.i = undo.i;
+        self.v = undo.v;
+        self.stack.clone_from(&undo.stack);
+        self.delay.set(undo.delay);
+        self.sound.set(undo.sound);
+
+        let (addr, bytes) = &undo.memory;
+        self.memory[*addr .. *addr + bytes.len()].copy_from_slice(bytes);
+
+        if self.hires != undo.hires {
+            self.set_resolution(undo.hires);
+        }
+        for (n, pixel) in undo.display.iter() {
+            self.display[*n] = *pixel;
+        }
+
+        self.keypad = undo.keypad;
+        self.rng.restore(self.rng.algorithm(), undo.rng);
+        if let (Some(movie), Some(position)) = (&mut self.movie, undo.movie) {
+            if let Err(err) = movie.seek(position) {
+                println!("Recording stopped: {err}");
+                self.movie = None;
+            }
+        }
+
+        self.cycles -= 1;
+    }
+
+    pub fn refresh(&mut self) {
+        self.draw();
     }
 }
 
@@ -104,23 +581,22 @@ impl Chip {
         println!("PC: {:04x}", self.pc);
         println!("I: {:04x}", self.i);
         println!("V:");
-        hex::dump(&self.v.iter().cloned().collect());
+        hex::dump(&self.v, 0);
     }
 
-    pub fn dump_memory(&self) {
-        println!("Memory dump:");
-        hex::dump(&self.memory);
-    }
+    pub fn dump_memory(&self, addr: usize, len: usize) {
+        let end = usize::min(addr + len, self.memory.len());
+        let font_end = FONT_MEMORY_OFFSET + font::get().len();
 
-    pub fn dump_stack(&self) {
-        if self.stack.len() == 0 {
-            println!("Call stack is empty");
-        } else {
-            println!("Call stack:");
-            for addr in self.stack.iter().rev() {
-                println!("{:04x}", addr);
-            }
-        }
+        let notes = [
+            (FONT_MEMORY_OFFSET, format!("font {FONT_MEMORY_OFFSET:04x}-{:04x}", font_end - 1)),
+            (PROGRAM_MEMORY_OFFSET, format!("program {PROGRAM_MEMORY_OFFSET:04x}")),
+            (self.pc as usize, format!("PC {:04x}", self.pc)),
+            (self.i as usize, format!("I {:04x}", self.i)),
+        ];
+
+        println!("Memory dump:");
+        hex::dump_annotated(&self.memory[addr .. end], addr, &notes);
     }
 }
 
@@ -149,8 +625,10 @@ impl Chip {
             Decoded::Draw(x, y, n)           => self.exec_draw(x, y, n),
             Decoded::FontChar(x)             => self.exec_font_char(x),
             Decoded::GetKey(x)               => self.exec_get_key(x),
+            Decoded::HighRes                 => self.set_resolution(true),
             Decoded::Jump(nnn)               => self.exec_jump(nnn),
             Decoded::Load(x)                 => self.exec_load(x),
+            Decoded::LowRes                  => self.set_resolution(false),
             Decoded::Move(x, nn)             => self.exec_mov(x, nn),
             Decoded::MoveIndex(nnn)          => self.exec_movi(nnn),
             Decoded::MoveXY(x, y)            => self.exec_mov_xy(x, y),
@@ -177,9 +655,8 @@ impl Chip {
 
     fn handle_illegal_instruction(&mut self, i: Instruction) {
         println!("Illegal instruction: {i:04x}");
-        while self.window.is_open() {
-            self.draw();
-        }
+        let (width, height) = self.display_size();
+        self.frontend.halt(&self.display, &self.palette, width, height);
     }
 }
 
@@ -253,36 +730,23 @@ impl Chip {
     }
 
     fn exec_skip_key(&mut self, x: Register) {
-        let want = byte_to_key(self.v[x]);
-        let have = self.window.get_keys();
-
-        if let Some(key) = have.get(0) {
-            if *key == want {
-                self.pc += 2;
-            }
+        if self.key_down(self.v[x]) {
+            self.pc += 2;
         }
     }
 
     fn exec_skip_not_key(&mut self, x: Register) {
-        let want = byte_to_key(self.v[x]);
-        let have = self.window.get_keys();
-
-        match have.get(0) {
-            Some(key) => if *key != want { self.pc += 2; },
-            None => self.pc += 2,
+        if !self.key_down(self.v[x]) {
+            self.pc += 2;
         }
     }
 
+    // Waits by running again until a key is held.
     fn exec_get_key(&mut self, x: Register) {
-        while self.window.is_open() {
-            let keys: Vec<Key> = self.window.get_keys();
-
-            if keys.len() == 0 {
-                self.draw();
-            } else {
-                self.v[x] = key_to_byte(keys[0]);
-                break;
-            }
+        if self.keypad == 0 {
+            self.pc -= 2;
+        } else {
+            self.v[x] = self.keypad.trailing_zeros() as Byte;
         }
     }
 }
@@ -340,8 +804,7 @@ impl Chip {
     }
 
     fn exec_rand(&mut self, x: Register, nn: Byte) {
-        let rn = rand::thread_rng().gen_range(0 ..= 255);
-        self.v[x] = rn & nn;
+        self.v[x] = self.rng.next_byte(self.vip_page.as_ref()) & nn;
     }
 
     fn exec_decimal(&mut self, x: Register) {
@@ -359,7 +822,8 @@ impl Chip {
 
 // Instructions for timers.
 impl Chip {
-    fn exec_set_sound_timer(&mut self, _x: Register) {
+    fn exec_set_sound_timer(&mut self, x: Register) {
+        self.sound.set(self.v[x]);
     }
 
     fn exec_delay_timer_set(&mut self, x: Register) {
@@ -384,91 +848,199 @@ impl Chip {
     }
 
     fn exec_draw(&mut self, x: Register, y: Register, n: Nibble) {
-        let vx = (self.v[x] % DISPLAY_WIDTH as u8) as usize;
-        let vy = (self.v[y] % DISPLAY_WIDTH as u8) as usize;
+        let (width, height) = self.display_size();
 
-        let mut y = vy;
+        // The sprite starts wrapped onto the display and is clipped at its
+        // right and bottom edges. In hi-res, DXY0 draws 16x16 from two bytes
+        // a row.
+        let vx = self.v[x] as usize % width;
+        let vy = self.v[y] as usize % height;
+        let (rows, columns) = if n == 0 && self.hires { (16, 16) } else { (n as usize, 8) };
+        let row_bytes = columns / 8;
 
         self.v[0xF] = 0;
 
-        for i in 0 .. n as usize {
-            let byte = self.memory[self.i as usize + i];
-            let mut x = vx;
-            for shift in (0 .. 8).rev() {
-                if byte >> shift & 1 == 1 {
-                    if self.display[y * DISPLAY_WIDTH + x] == 0xFFFFFF {
+        for i in 0 .. rows {
+            let at = self.i as usize + i * row_bytes;
+            let bits = self.memory[at .. at + row_bytes].iter().fold(0u16, |bits, byte| bits << 8 | *byte as u16);
+            let y = vy + i;
+            if y >= height {
+                break;
+            }
+            for (j, shift) in (0 .. columns).rev().enumerate() {
+                let x = vx + j;
+                if x < width && bits >> shift & 1 == 1 {
+                    if self.display[y * width + x] & 1 != 0 {
                         self.v[0xF] = 1;
                         self.clear_pixel(x, y);
                     } else {
                         self.set_pixel(x, y);
                     }
                 }
-                x += 1;
             }
-            y += 1;
         }
-
-        self.draw();
     }
 }
 
 // Methods for handling the display and window.
 impl Chip {
+    // Pixels hold colour numbers, with a bit for each plane. Only the first
+    // plane is drawn to.
     fn set_pixel(&mut self, x: usize, y: usize) {
-        self.display[y * DISPLAY_WIDTH + x] = 0xFFFFFF;
+        let width = self.display_size().0;
+        self.display[y * width + x] |= 1;
     }
 
     fn clear_pixel(&mut self, x: usize, y: usize) {
-        self.display[y * DISPLAY_WIDTH + x] = 0;
+        let width = self.display_size().0;
+        self.display[y * width + x] &= !1;
+    }
+
+    // Switching resolution clears the display. The frontends lay out each
+    // frame by its size, so the window keeps its size either way.
+    fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = self.display_size();
+        self.display = vec![0; width * height];
     }
 
     fn draw(&mut self) {
-        self.window
-            .update_with_buffer(&self.display, DISPLAY_WIDTH, DISPLAY_HEIGHT)
-            .unwrap();
+        let (width, height) = self.display_size();
+        self.frontend.present(&self.display, &self.palette, width, height);
     }
-}
 
-fn key_to_byte(key: Key) -> Byte {
-    match key {
-        Key::Key1 => 0x1,
-        Key::Key2 => 0x2,
-        Key::Key3 => 0x3,
-        Key::Key4 => 0xC,
-        Key::Q => 0x4,
-        Key::W => 0x5,
-        Key::E => 0x6,
-        Key::R => 0xD,
-        Key::A => 0x7,
-        Key::S => 0x8,
-        Key::D => 0x9,
-        Key::F => 0xE,
-        Key::Z => 0xA,
-        Key::X => 0x0,
-        Key::C => 0xB,
-        Key::V => 0xF,
-        _ => panic!("Unhandled key press: {:?}", key),
+    fn key_down(&self, key: Byte) -> bool {
+        self.keypad & 1 << (key & 0xF) != 0
     }
 }
 
-fn byte_to_key(byte: Byte) -> Key {
-    match byte {
-        0x1 => Key::Key1,
-        0x2 => Key::Key2,
-        0x3 => Key::Key3,
-        0xC => Key::Key4,
-        0x4 => Key::Q,
-        0x5 => Key::W,
-        0x6 => Key::E,
-        0xD => Key::R,
-        0x7 => Key::A,
-        0x8 => Key::S,
-        0x9 => Key::D,
-        0xE => Key::F,
-        0xA => Key::Z,
-        0x0 => Key::X,
-        0xB => Key::C,
-        0xF => Key::V,
-        _ => panic!("Unhandled byte-to-key: {byte}"),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_rom(rom: &[u8], steps: usize) -> Chip {
+        let mut chip = Chip::new(Frontend::headless());
+        chip.load_font();
+        chip.load_rom(rom.to_vec());
+        for _ in 0 .. steps {
+            chip.step();
+        }
+        chip
+    }
+
+    #[test]
+    fn draw_clips_at_edges() {
+        // The "0" glyph, F0 90 90 90 F0, at 62,30.
+        let chip = run_rom(&[0x60, 62, 0x61, 30, 0xA0, 0x00, 0xD0, 0x15], 4);
+
+        assert!(chip.pixel(62, 30) && chip.pixel(63, 30));
+        assert!(chip.pixel(62, 31) && !chip.pixel(63, 31));
+        assert!(!chip.pixel(0, 30) && !chip.pixel(0, 0) && !chip.pixel(62, 0));
+        assert_eq!(chip.v(0xF), 0);
+    }
+
+    #[test]
+    fn undo_restores_random_numbers() {
+        let mut chip = run_rom(&[0xC0, 0xFF, 0xC0, 0xFF], 0);
+        chip.set_rng(Rng::new(99, Algorithm::Xorshift)).unwrap();
+
+        let undo = chip.step_undoable();
+        let first = chip.v(0);
+        chip.undo(&undo);
+        chip.step();
+        assert_eq!(chip.v(0), first);
+    }
+
+    #[test]
+    fn undo_restores_keypad() {
+        let mut chip = run_rom(&[0x12, 0x00], STEPS_PER_FRAME as usize - 1);
+        chip.keypad = 1 << 5;
+
+        // The step ends the frame, sampling the headless frontend's keypad.
+        let undo = chip.step_undoable();
+        assert_eq!(chip.keypad, 0);
+        chip.undo(&undo);
+        assert_eq!(chip.keypad, 1 << 5);
+    }
+
+    #[test]
+    fn vip_needs_page() {
+        let mut chip = run_rom(&[0xC0, 0xFF], 0);
+        assert!(chip.set_rng(Rng::new(0, Algorithm::Vip)).is_err());
+
+        chip.set_vip_page([7; 256]);
+        chip.set_rng(Rng::new(0x0100, Algorithm::Vip)).unwrap();
+        chip.step();
+        assert_eq!(chip.v(0), 8);
+    }
+
+    #[test]
+    fn headless_run_ends_with_movie() {
+        let path = std::env::temp_dir().join(format!("nn-chip-{}.nnm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let rom = [0x70, 0x01, 0x12, 0x00];
+
+        let mut movie = Movie::record(path, 0, Algorithm::Xorshift, &rom).unwrap();
+        for _ in 0 .. 3 {
+            movie.frame(0);
+        }
+        drop(movie);
+        let (movie, _) = Movie::play(path, &rom).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut chip = run_rom(&rom, 0);
+        chip.set_movie(movie);
+        assert!(!chip.run(None));
+        assert!(chip.movie.is_none());
+        assert_eq!(chip.v(0) as u64, 4 * STEPS_PER_FRAME / 2);
+    }
+
+    #[test]
+    fn hires_draws_large_sprites() {
+        // HIGH; LD I, 0x300; DRW V0, V0, 0 with a 16x16 sprite of the top
+        // row and left column at 0x300.
+        let mut chip = run_rom(&[0x00, 0xFF, 0xA3, 0x00, 0xD0, 0x00], 0);
+        chip.write_memory(0x300, &[0xFF, 0xFF]);
+        for row in 1 .. 16 {
+            chip.write_memory(0x300 + 2 * row, &[0x80, 0x00]);
+        }
+
+        chip.step();
+        assert_eq!(chip.display_size(), (128, 64));
+        chip.step();
+        chip.step();
+        assert!(chip.pixel(15, 0) && chip.pixel(0, 15));
+        assert!(!chip.pixel(16, 0) && !chip.pixel(1, 1));
+    }
+
+    #[test]
+    fn undo_and_restore_resolution() {
+        let mut chip = run_rom(&[0xA0, 0x00, 0xD0, 0x15, 0x00, 0xFF, 0x00, 0xFE], 2);
+        let low = chip.state();
+
+        let undo = chip.step_undoable();
+        assert_eq!(chip.display_size(), (128, 64));
+        assert!(!chip.pixel(0, 0));
+        let high = chip.state();
+
+        chip.undo(&undo);
+        assert_eq!(chip.display_size(), (64, 32));
+        assert!(chip.pixel(0, 0));
+
+        chip.restore(&high).unwrap();
+        assert_eq!(chip.display_size(), (128, 64));
+        chip.step();
+        assert_eq!(chip.display_size(), (64, 32));
+        assert!(!chip.pixel(0, 0));
+        chip.restore(&low).unwrap();
+        assert!(chip.pixel(0, 0));
+    }
+
+    #[test]
+    fn draw_wraps_start() {
+        let chip = run_rom(&[0x60, 64 + 8, 0x61, 32 + 4, 0xA0, 0x00, 0xD0, 0x15], 4);
+
+        assert!(chip.pixel(8, 4) && chip.pixel(11, 4) && chip.pixel(8, 8));
+        assert!(!chip.pixel(9, 5));
     }
 }
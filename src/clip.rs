@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use crate::gif::Gif;
+use crate::image::Image;
+
+// Recordings of the display, one frame for each 60th of a second, as an
+// animated GIF or as numbered PNGs (clip-00001.png, clip-00002.png, ... for
+// clip.png) to make into a video with other tools. Frames keep the size the
+// clip started with, stretched to it when the resolution changes.
+//
+// GIF delays are in hundredths of a second, and viewers slow down frames
+// shown for less than two, so a frame that changes sooner is replaced by
+// the next rather than written. Runs of unchanged frames become one.
+
+const FRAMES_PER_SECOND: u64 = 60;
+const MIN_DELAY: u64 = 2;
+
+pub struct Clip {
+    path: String,
+    width: usize,
+    height: usize,
+    frames: u64,
+    kind: Kind,
+}
+
+enum Kind {
+    Gif {
+        gif: Option<Gif>,
+        // The frame waiting to be written, and the frame number it started at.
+        shown: Option<(Image, u64)>,
+    },
+    Png {
+        stem: String,
+    },
+}
+
+impl Clip {
+    pub fn create(path: &str, width: usize, height: usize, scale: usize) -> Result<Self, String> {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+
+        let kind = match extension.to_lowercase().as_str() {
+            "gif" => {
+                let gif = Gif::create(path, width * scale, height * scale).map_err(|err| format!("{path}: {err}"))?;
+                Kind::Gif { gif: Some(gif), shown: None }
+            },
+            "png" => Kind::Png { stem: path[.. path.len() - ".png".len()].to_string() },
+            _ => return Err(format!("{path}: unknown clip format, use .gif or .png")),
+        };
+
+        Ok(Self { path: path.to_string(), width: width * scale, height: height * scale, frames: 0, kind })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn frame(&mut self, image: Image) -> Result<(), String> {
+        let frame = self.frames;
+        self.frames += 1;
+
+        match &mut self.kind {
+            Kind::Gif { gif: Some(gif), shown } => match shown {
+                None => *shown = Some((image, frame)),
+                Some((last, _)) if *last == image => (),
+                Some((last, start)) => {
+                    let delay = centiseconds(frame) - centiseconds(*start);
+                    if delay < MIN_DELAY {
+                        *last = image;
+                    } else {
+                        gif.frame(&last.resized(self.width, self.height), delay.min(u16::MAX as u64) as u16).map_err(|err| format!("{}: {err}", self.path))?;
+                        *shown = Some((image, frame));
+                    }
+                },
+            },
+            Kind::Gif { gif: None, .. } => (),
+            Kind::Png { stem } => image.resized(self.width, self.height).save(&format!("{stem}-{:05}.png", frame + 1))?,
+        }
+
+        Ok(())
+    }
+
+    // Writes out what is left. Dropping a clip finishes it too, but quietly.
+    pub fn finish(&mut self) -> Result<(), String> {
+        if let Kind::Gif { gif, shown } = &mut self.kind {
+            if let Some(mut gif) = gif.take() {
+                if let Some((last, start)) = shown.take() {
+                    let delay = (centiseconds(self.frames) - centiseconds(start)).max(MIN_DELAY);
+                    gif.frame(&last.resized(self.width, self.height), delay.min(u16::MAX as u64) as u16).map_err(|err| format!("{}: {err}", self.path))?;
+                }
+                gif.finish().map_err(|err| format!("{}: {err}", self.path))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Clip {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn centiseconds(frame: u64) -> u64 {
+    frame * 100 / FRAMES_PER_SECOND
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records one frame per colour and returns the delays of the GIF.
+    fn delays(colours: &[u32]) -> Vec<u16> {
+        let path = std::env::temp_dir().join(format!("nn-clip-{}-{}.gif", std::process::id(), colours.len()));
+        let path = path.to_str().unwrap();
+
+        let mut clip = Clip::create(path, 2, 1, 1).unwrap();
+        for colour in colours {
+            clip.frame(Image::new(&[*colour, 0], 2, 1)).unwrap();
+        }
+        clip.finish().unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut delays = Vec::new();
+        let mut at = 13 + 19;
+        while bytes[at] != 0x3B {
+            assert_eq!(bytes[at .. at + 4], [0x21, 0xF9, 4, 0x04]);
+            delays.push(u16::from_le_bytes([bytes[at + 4], bytes[at + 5]]));
+            at += 8;
+
+            let bits = (bytes[at + 9] & 7) + 1;
+            at += 10 + 3 * (1 << bits) + 1;
+            while bytes[at] != 0 {
+                at += bytes[at] as usize + 1;
+            }
+            at += 1;
+        }
+        delays
+    }
+
+    #[test]
+    fn unchanged_frames_become_one() {
+        assert_eq!(delays(&[1; 120]), [200]);
+        assert_eq!(delays(&[[1; 30], [2; 30], [3; 30], [4; 30]].concat()), [50, 50, 50, 50]);
+    }
+
+    #[test]
+    fn short_frames_are_replaced() {
+        // Frames 1 and 4 last a hundredth of a second, too short to show.
+        assert_eq!(delays(&[1, 2, 2, 3, 4, 4, 4, 4, 4, 4]), [5, 11]);
+    }
+
+    #[test]
+    fn frames_keep_their_size() {
+        let stem = std::env::temp_dir().join(format!("nn-clip-{}", std::process::id()));
+        let stem = stem.to_str().unwrap();
+
+        let mut clip = Clip::create(&format!("{stem}.png"), 2, 1, 2).unwrap();
+        clip.frame(Image::new(&[1, 0], 2, 1)).unwrap();
+        clip.frame(Image::new(&[1; 8], 4, 2)).unwrap();
+
+        for n in 1 ..= 2 {
+            let path = format!("{stem}-{n:05}.png");
+            let png = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(png[16 .. 24], [0, 0, 0, 4, 0, 0, 0, 2]);
+        }
+    }
+
+    #[test]
+    fn delays_add_up() {
+        let colours: Vec<u32> = (0 .. 600).map(|n| n / 7).collect();
+        let delays = delays(&colours);
+        assert_eq!(delays.iter().map(|d| *d as u64).sum::<u64>(), centiseconds(600));
+        assert!(delays.iter().all(|d| *d as u64 >= MIN_DELAY));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::expr::{self, Expr, Var};
+use crate::text_screen::Style;
+use crate::types::*;
+
+// A breakpoint condition and its source text.
+pub type Condition = (String, Expr);
+
+pub enum Command {
+    Instruction,
+    List(Option<Expr>),
+    Registers,
+    Memory(Option<(Expr, Option<Expr>)>),
+    Stack,
+    Screen(Style, bool),
+    Step(Option<Expr>),
+    StepLine,
+    Next,
+    Finish,
+    Continue,
+    ReverseStep(Option<Expr>),
+    ReverseContinue,
+    Record(Option<Expr>),
+    Break(Option<(Expr, Option<Condition>)>),
+    Delete(Expr),
+    Set(Var, Expr),
+    Write(Expr, Vec<Byte>),
+    Fill(Expr, Expr, Byte),
+    Push(Expr),
+    Pop,
+    Screenshot(Option<String>, Option<Expr>),
+    Save(Option<String>),
+    Load(Option<String>),
+    Help,
+    Quit,
+}
+
+pub fn help() {
+    println!("Commands (short forms in brackets):");
+    println!("  .                        show next instruction");
+    println!("  list (u) [addr]          disassemble around addr (default PC)");
+    println!("  regs (r)                 dump registers");
+    println!("  mem (m) [addr [len]]     dump all memory, or len (default 64) bytes at addr, e.g. m i");
+    println!("  stack (c)                dump call stack");
+    println!("  screen (v) [braille] [changes]");
+    println!("                           draw the display as text, highlighting pixels changed");
+    println!("                           by the last step if asked");
+    println!("  step (s) [n]             step one (or n) instructions");
+    println!("  step-line (sl)           step to the next source line");
+    println!("  next (n)                 step over subroutine calls");
+    println!("  finish (f)               run until the current subroutine returns");
+    println!("  continue (g)             run until a breakpoint is hit");
+    println!("  reverse-step (rs) [n]    step back one (or n) instructions");
+    println!("  reverse-continue (rc)    run backwards until a breakpoint is hit");
+    println!("  record [size]            show or set how many steps are kept for going back");
+    println!("  break (b)                list breakpoints");
+    println!("  break (b) addr [if cond] set breakpoint, e.g. b 0x2a4 if v0 == 5 && [i+2] != 0");
+    println!("  delete (d) addr          delete breakpoint");
+    println!("  set reg value            set v0-vf, i, pc, dt or st, e.g. set v3 0x10");
+    println!("  write (w) addr byte...   write hex bytes to memory, e.g. w 0x300 de ad be ef");
+    println!("  fill addr len byte       fill memory with a hex byte");
+    println!("  push addr                push an address onto the call stack");
+    println!("  pop                      pop an address off the call stack");
+    println!("  screenshot [file [scale]]");
+    println!("                           save the display as .png, .pbm or .pgm (default");
+    println!("                           rom_path-n.png, scale 8)");
+    println!("  save [file]              save the machine state (default rom_path.state)");
+    println!("  load [file]              load a saved machine state");
+    println!("  help (h)                 show this help");
+    println!("  quit (q)                 quit");
+    println!("Addresses may be labels from a symbol file, e.g. b draw_player+4.");
+    println!("An empty line repeats the last step, step-line, next, reverse-step, mem or list.");
+    println!("While running, F12 in the window or Ctrl-C breaks back to the prompt.");
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let (cmd, args) = split(line);
+
+    let command = match cmd {
+        "." => Command::Instruction,
+        "u" | "list" => Command::List(optional(args)?),
+        "r" | "regs" => Command::Registers,
+        "m" | "mem" => Command::Memory(memory(args)?),
+        "c" | "stack" => Command::Stack,
+        "v" | "screen" => screen(args)?,
+        "sl" | "step-line" => Command::StepLine,
+        "s" | "step" => Command::Step(optional(args)?),
+        "n" | "next" => Command::Next,
+        "f" | "finish" => Command::Finish,
+        "g" | "go" | "continue" => Command::Continue,
+        "rs" | "reverse-step" => Command::ReverseStep(optional(args)?),
+        "rc" | "reverse-continue" => Command::ReverseContinue,
+        "record" => Command::Record(optional(args)?),
+        "b" | "break" => Command::Break(breakpoint(args)?),
+        "d" | "delete" => Command::Delete(expr::parse(args)?),
+        "set" => set(args)?,
+        "w" | "write" => write(args)?,
+        "fill" => fill(args)?,
+        "push" => Command::Push(expr::parse(args)?),
+        "pop" => Command::Pop,
+        "shot" | "screenshot" => screenshot(args)?,
+        "save" => Command::Save(path(args)),
+        "load" => Command::Load(path(args)),
+        "h" | "help" => Command::Help,
+        "q" | "quit" => Command::Quit,
+        _ => return Err(format!("unknown command: {cmd}")),
+    };
+
+    Ok(command)
+}
+
+fn split(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.split_once(char::is_whitespace) {
+        Some((cmd, args)) => (cmd, args.trim_start()),
+        None => (line, ""),
+    }
+}
+
+fn optional(args: &str) -> Result<Option<Expr>, String> {
+    match args {
+        "" => Ok(None),
+        _ => expr::parse(args).map(Some),
+    }
+}
+
+fn screenshot(args: &str) -> Result<Command, String> {
+    let args: Vec<&str> = args.split_whitespace().collect();
+
+    match args[..] {
+        [] => Ok(Command::Screenshot(None, None)),
+        [path] => Ok(Command::Screenshot(Some(path.to_string()), None)),
+        [path, scale] => Ok(Command::Screenshot(Some(path.to_string()), Some(expr::parse(scale)?))),
+        _ => Err("usage: screenshot [file [scale]]".to_string()),
+    }
+}
+
+fn path(args: &str) -> Option<String> {
+    match args {
+        "" => None,
+        _ => Some(args.to_string()),
+    }
+}
+
+fn memory(args: &str) -> Result<Option<(Expr, Option<Expr>)>, String> {
+    let args: Vec<&str> = args.split_whitespace().collect();
+
+    match args[..] {
+        [] => Ok(None),
+        [addr] => Ok(Some((expr::parse(addr)?, None))),
+        [addr, len] => Ok(Some((expr::parse(addr)?, Some(expr::parse(len)?)))),
+        _ => Err("usage: mem [addr [len]]".to_string()),
+    }
+}
+
+fn breakpoint(args: &str) -> Result<Option<(Expr, Option<Condition>)>, String> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+
+    let (addr, cond) = match args.split_once(" if ") {
+        Some((addr, cond)) => (addr, Some(cond.trim())),
+        None => (args, None),
+    };
+
+    let cond = match cond {
+        Some(s) => Some((s.to_string(), expr::parse(s)?)),
+        None => None,
+    };
+
+    Ok(Some((expr::parse(addr)?, cond)))
+}
+
+fn screen(args: &str) -> Result<Command, String> {
+    let mut style = Style::Blocks;
+    let mut changes = false;
+
+    for arg in args.split_whitespace() {
+        match arg {
+            "blocks" => style = Style::Blocks,
+            "braille" => style = Style::Braille,
+            "changes" => changes = true,
+            _ => return Err("usage: screen [blocks|braille] [changes]".to_string()),
+        }
+    }
+
+    Ok(Command::Screen(style, changes))
+}
+
+fn set(args: &str) -> Result<Command, String> {
+    let (name, value) = split(args);
+    Ok(Command::Set(expr::var(name)?, expr::parse(value)?))
+}
+
+fn write(args: &str) -> Result<Command, String> {
+    let (addr, bytes) = split(args);
+
+    let bytes = bytes
+        .split_whitespace()
+        .map(parse_byte)
+        .collect::<Result<Vec<Byte>, String>>()?;
+
+    Ok(Command::Write(expr::parse(addr)?, bytes))
+}
+
+fn fill(args: &str) -> Result<Command, String> {
+    match args.split_whitespace().collect::<Vec<&str>>()[..] {
+        [addr, len, byte] => Ok(Command::Fill(expr::parse(addr)?, expr::parse(len)?, parse_byte(byte)?)),
+        _ => Err("usage: fill addr len byte".to_string()),
+    }
+}
+
+fn parse_byte(s: &str) -> Result<Byte, String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    Byte::from_str_radix(hex, 16).map_err(|_| format!("bad byte: {s}"))
+}
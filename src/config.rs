@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+// Settings read from a file ahead of the command line, which overrides them.
+// Each line is "name = value", where name is an option without its dashes
+// from KEYS, or from FLAGS with a value of true or false, or a comment
+// starting with #:
+//
+//   theme = amber
+//   palette = 000000, ffcc00
+//   fullscreen = true
+//
+// The file is the one given with --config, or else $XDG_CONFIG_HOME/nn/config
+// or ~/.config/nn/config if there is one.
+
+pub const KEYS: [&str; 3] = ["theme", "palette", "scale"];
+pub const FLAGS: [&str; 2] = ["fullscreen", "resizable"];
+
+pub fn default_path() -> Option<String> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    let path = dir.join("nn").join("config");
+
+    if path.exists() {
+        path.to_str().map(|path| path.to_string())
+    } else {
+        None
+    }
+}
+
+// Reads the settings in path as command line arguments.
+pub fn load(path: &str) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    let mut args = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(format!("{path}:{}: expected name = value", n + 1)),
+        };
+
+        if KEYS.contains(&key) {
+            args.push(format!("--{key}"));
+            args.push(value.to_string());
+        } else if FLAGS.contains(&key) {
+            match value {
+                "true" => args.push(format!("--{key}")),
+                "false" => (),
+                _ => return Err(format!("{path}:{}: {key} must be true or false", n + 1)),
+            }
+        } else {
+            return Err(format!("{path}:{}: unknown setting: {key}", n + 1));
+        }
+    }
+
+    Ok(args)
+}
@@ -0,0 +1,721 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::base64;
+use crate::chip::Chip;
+use crate::debugger::{Debugger, Goal, Stop};
+use crate::decode;
+use crate::disasm;
+use crate::expr;
+use crate::frontend::{Frontend, WindowSettings};
+use crate::json::{self, object, Value};
+use crate::palette::Palette;
+use crate::symbols::Symbols;
+use crate::types::*;
+
+// A debug adapter protocol server over stdin and stdout. Stdout carries the
+// protocol, so nothing else may be printed there while serving.
+//
+// The launch request names the program and optionally a symbol file, which
+// otherwise comes from --symbols. Its source lines take breakpoints set in
+// source files, and show where stopped frames are.
+
+const THREAD_ID: i64 = 1;
+const STEPS_PER_POLL: usize = 1000;
+const REGISTERS_REFERENCE: i64 = 1;
+const TIMERS_REFERENCE: i64 = 2;
+// Requests are small; a larger Content-Length ends the session.
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+enum State {
+    Stopped,
+    Running(Goal),
+}
+
+struct Adapter<W> {
+    out: W,
+    seq: i64,
+    window: WindowSettings,
+    headless: bool,
+    palette: Palette,
+    symbols_path: Option<String>,
+    debugger: Option<Debugger>,
+    stop_on_entry: bool,
+    breakpoints: HashSet<Address>,
+    // Breakpoints set on source lines, by file.
+    source_breakpoints: HashMap<String, Vec<Address>>,
+    state: State,
+}
+
+pub fn serve(window: WindowSettings, headless: bool, palette: Palette, symbols_path: Option<String>) {
+    let rx = spawn_reader();
+    let mut adapter = Adapter {
+        out: io::stdout(),
+        seq: 1,
+        window,
+        headless,
+        palette,
+        symbols_path,
+        debugger: None,
+        stop_on_entry: false,
+        breakpoints: HashSet::new(),
+        source_breakpoints: HashMap::new(),
+        state: State::Stopped,
+    };
+
+    loop {
+        let message = match adapter.state {
+            State::Stopped => match rx.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            },
+            State::Running(_) => match rx.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            },
+        };
+
+        if let Some(message) = message {
+            if !adapter.handle(&message) {
+                break;
+            }
+        }
+
+        if let State::Running(_) = adapter.state {
+            if !adapter.run() {
+                break;
+            }
+        }
+    }
+}
+
+fn spawn_reader() -> Receiver<Value> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Some(message) = read_message(&mut stdin) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut len = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse().ok();
+        }
+    }
+
+    let len = len.filter(|len| *len <= MAX_MESSAGE_SIZE)?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body).ok()?;
+
+    match json::parse(&String::from_utf8_lossy(&body)) {
+        Ok(message) => Some(message),
+        Err(_) => Some(Value::Null),
+    }
+}
+
+impl<W: Write> Adapter<W> {
+    fn send(&mut self, mut message: Vec<(&str, Value)>) {
+        message.insert(0, ("seq", self.seq.into()));
+        self.seq += 1;
+
+        let body = object(message).to_string();
+        let _ = write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.out.flush();
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(vec![
+            ("type", "event".into()),
+            ("event", event.into()),
+            ("body", body),
+        ]);
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) {
+        self.state = State::Stopped;
+
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(description) = description {
+            body.push(("description", description.into()));
+        }
+
+        self.event("stopped", object(body));
+    }
+
+    // Returns false when the session is over.
+    fn handle(&mut self, request: &Value) -> bool {
+        let command = request.get("command").as_str().unwrap_or("").to_string();
+        let args = request.get("arguments");
+
+        let result = match command.as_str() {
+            "initialize" => Ok(capabilities()),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_source_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(object(vec![("breakpoints", Value::Array(Vec::new()))])),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(threads()),
+            "stackTrace" => self.with_debugger(stack_trace),
+            "scopes" => Ok(scopes()),
+            "variables" => self.with_debugger(|debugger| variables(debugger.chip(), args)),
+            "evaluate" => self.with_debugger(|debugger| evaluate(debugger, args)),
+            "readMemory" => self.with_debugger(|debugger| read_memory(debugger.chip(), args)),
+            "disassemble" => self.with_debugger(|debugger| disassemble(debugger, args)),
+            "continue" => self.resume(|_| Ok(Goal::Never)).map(|_| object(vec![("allThreadsContinued", true.into())])),
+            "next" => self.resume(|debugger| Ok(debugger.next_goal())),
+            "stepIn" => self.resume(|_| Ok(Goal::Steps(1))),
+            "stepOut" => self.resume(Debugger::finish_goal),
+            "pause" => Ok(Value::Null),
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(format!("unsupported request: {command}")),
+        };
+
+        let success = result.is_ok();
+        let mut response = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("success", success.into()),
+            ("command", command.as_str().into()),
+        ];
+        match result {
+            Ok(body) => response.push(("body", body)),
+            Err(err) => response.push(("message", err.into())),
+        }
+        self.send(response);
+
+        match command.as_str() {
+            "initialize" => self.event("initialized", Value::Null),
+            "configurationDone" => self.start(),
+            "pause" => self.stopped("pause", None),
+            "disconnect" | "terminate" => return false,
+            _ => (),
+        }
+
+        true
+    }
+
+    fn with_debugger<F>(&self, f: F) -> Result<Value, String>
+    where
+        F: FnOnce(&Debugger) -> Result<Value, String>,
+    {
+        match &self.debugger {
+            Some(debugger) => f(debugger),
+            None => Err("no program launched".to_string()),
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args.get("program").as_str().ok_or("launch needs a program")?;
+        let rom = std::fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+
+        let symbols = match args.get("symbols").as_str().or(self.symbols_path.as_deref()) {
+            Some(path) => Symbols::load(path)?,
+            None => Symbols::default(),
+        };
+
+        let frontend = if self.headless { Frontend::headless() } else { Chip::window(self.window) };
+        let mut chip = Chip::new(frontend);
+        chip.set_palette(self.palette);
+        chip.load_font();
+        chip.load_rom(rom);
+
+        // Stdout is for the protocol, so the hotkeys cannot report.
+        let mut debugger = Debugger::new(chip, symbols);
+        debugger.disable_hotkeys();
+        self.debugger = Some(debugger);
+        self.sync_breakpoints();
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+
+        Ok(Value::Null)
+    }
+
+    fn start(&mut self) {
+        if self.debugger.is_none() {
+            return;
+        }
+
+        if self.stop_on_entry {
+            self.stopped("entry", None);
+        } else {
+            self.state = State::Running(Goal::Never);
+        }
+    }
+
+    // Sets the breakpoints in one source file, each on the first instruction
+    // of its line or the next line with code.
+    fn set_source_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let symbols = self.debugger.as_ref().ok_or("no program launched")?.symbols();
+        let file = args.get("source").get("path").as_str().unwrap_or("").to_string();
+        let mut addrs = Vec::new();
+        let mut verified = Vec::new();
+
+        for bp in args.get("breakpoints").as_array() {
+            let line = bp.get("line").as_i64().unwrap_or(0).max(0) as usize;
+
+            match symbols.line_address(&file, line) {
+                Some((addr, line)) => {
+                    addrs.push(addr);
+                    verified.push(object(vec![
+                        ("verified", true.into()),
+                        ("line", (line as i64).into()),
+                        ("instructionReference", address(addr as usize)),
+                    ]));
+                },
+                None => verified.push(object(vec![
+                    ("verified", false.into()),
+                    ("message", "no code at or after this line".into()),
+                ])),
+            }
+        }
+
+        self.source_breakpoints.insert(file, addrs);
+        self.sync_breakpoints();
+        Ok(object(vec![("breakpoints", verified.into())]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let mut verified = Vec::new();
+        self.breakpoints.clear();
+
+        for bp in args.get("breakpoints").as_array() {
+            let addr = bp
+                .get("instructionReference")
+                .as_str()
+                .and_then(|s| expr::parse_number(s).ok())
+                .and_then(|addr| (addr as i64).checked_add(bp.get("offset").as_i64().unwrap_or(0)))
+                .and_then(|addr| Address::try_from(addr).ok());
+
+            match addr {
+                Some(addr) => {
+                    self.breakpoints.insert(addr);
+                    verified.push(object(vec![("verified", true.into())]));
+                },
+                None => verified.push(object(vec![("verified", false.into())])),
+            }
+        }
+
+        self.sync_breakpoints();
+        Ok(object(vec![("breakpoints", verified.into())]))
+    }
+
+    // Gives the debugger the instruction and source breakpoints together.
+    fn sync_breakpoints(&mut self) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.clear_breakpoints();
+            for addr in self.breakpoints.iter().chain(self.source_breakpoints.values().flatten()) {
+                debugger.set_breakpoint(*addr, None);
+            }
+        }
+    }
+
+    // Runs on towards the goal, reporting the stop from run.
+    fn resume<F>(&mut self, goal: F) -> Result<Value, String>
+    where
+        F: FnOnce(&Debugger) -> Result<Goal, String>,
+    {
+        let debugger = self.debugger.as_ref().ok_or("no program launched")?;
+        self.state = State::Running(goal(debugger)?);
+        Ok(Value::Null)
+    }
+
+    // Runs a batch of instructions. Returns false when the window is closed.
+    fn run(&mut self) -> bool {
+        let (debugger, State::Running(goal)) = (&mut self.debugger, &mut self.state) else {
+            return true;
+        };
+        let stop = match debugger.as_mut().map(|debugger| debugger.run_for(goal, STEPS_PER_POLL)) {
+            Some(Some(stop)) => stop,
+            _ => return true,
+        };
+
+        match stop {
+            Stop::Done => self.stopped("step", None),
+            Stop::Breakpoint(addr, _) if self.breakpoints.contains(&addr) => self.stopped("instruction breakpoint", None),
+            Stop::Breakpoint(..) => self.stopped("breakpoint", None),
+            Stop::ConditionError(_, err) => self.stopped("breakpoint", Some(err)),
+            Stop::Interrupted(_) => self.stopped("pause", None),
+            Stop::Illegal(_, i) => self.stopped("exception", Some(format!("Illegal instruction: {i:04x}"))),
+            Stop::Closed => {
+                self.event("terminated", Value::Null);
+                return false;
+            },
+        }
+
+        true
+    }
+}
+
+fn capabilities() -> Value {
+    object(vec![
+        ("supportsConfigurationDoneRequest", true.into()),
+        ("supportsInstructionBreakpoints", true.into()),
+        ("supportsDisassembleRequest", true.into()),
+        ("supportsReadMemoryRequest", true.into()),
+        ("supportsEvaluateForHovers", true.into()),
+    ])
+}
+
+fn threads() -> Value {
+    let thread = object(vec![("id", THREAD_ID.into()), ("name", "CHIP-8".into())]);
+    object(vec![("threads", vec![thread].into())])
+}
+
+fn address(addr: usize) -> Value {
+    format!("0x{addr:04x}").into()
+}
+
+fn stack_trace(debugger: &Debugger) -> Result<Value, String> {
+    let (chip, symbols) = (debugger.chip(), debugger.symbols());
+    let labels = debugger.labels();
+    let mut pcs = vec![chip.pc()];
+    pcs.extend(chip.stack().iter().rev());
+
+    let frames: Vec<Value> = pcs
+        .iter()
+        .enumerate()
+        .map(|(id, pc)| {
+            let name = match labels.get(pc) {
+                Some(label) => label.clone(),
+                None => format!("{pc:04x}"),
+            };
+            let mut frame = vec![
+                ("id", (id as i64).into()),
+                ("name", name.into()),
+                ("line", 0.into()),
+                ("column", 0.into()),
+                ("instructionPointerReference", address(*pc as usize)),
+            ];
+            if let Some(location) = symbols.location(*pc) {
+                frame[2] = ("line", (location.line as i64).into());
+                frame.push(("source", object(vec![
+                    ("name", location.file.as_str().into()),
+                    ("path", symbols.path(location).to_string_lossy().to_string().into()),
+                ])));
+            }
+            object(frame)
+        })
+        .collect();
+
+    let total = frames.len() as i64;
+    Ok(object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())]))
+}
+
+fn scopes() -> Value {
+    let scope = |name: &str, reference: i64| object(vec![
+        ("name", name.into()),
+        ("variablesReference", reference.into()),
+        ("expensive", false.into()),
+    ]);
+
+    object(vec![("scopes", vec![
+        scope("Registers", REGISTERS_REFERENCE),
+        scope("Timers", TIMERS_REFERENCE),
+    ].into())])
+}
+
+fn variable(name: &str, value: String) -> Value {
+    object(vec![
+        ("name", name.into()),
+        ("value", value.into()),
+        ("variablesReference", 0.into()),
+    ])
+}
+
+fn variables(chip: &Chip, args: &Value) -> Result<Value, String> {
+    let mut vars = Vec::new();
+
+    match args.get("variablesReference").as_i64() {
+        Some(REGISTERS_REFERENCE) => {
+            for x in 0 .. 16 {
+                vars.push(variable(&format!("V{x:X}"), format!("0x{:02x}", chip.v(x))));
+            }
+            let mut i = variable("I", format!("0x{:04x}", chip.i()));
+            if let Value::Object(pairs) = &mut i {
+                pairs.push(("memoryReference".to_string(), address(chip.i() as usize)));
+            }
+            vars.push(i);
+            vars.push(variable("PC", format!("0x{:04x}", chip.pc())));
+            vars.push(variable("SP", chip.stack_depth().to_string()));
+        },
+        Some(TIMERS_REFERENCE) => {
+            vars.push(variable("DT", chip.delay_timer().to_string()));
+            vars.push(variable("ST", chip.sound_timer().to_string()));
+        },
+        _ => (),
+    }
+
+    Ok(object(vec![("variables", vars.into())]))
+}
+
+fn evaluate(debugger: &Debugger, args: &Value) -> Result<Value, String> {
+    let source = args.get("expression").as_str().unwrap_or("");
+    let value = debugger.eval(&expr::parse(source)?)?;
+
+    Ok(object(vec![
+        ("result", format!("{value} (0x{value:x})").into()),
+        ("variablesReference", 0.into()),
+    ]))
+}
+
+fn memory_reference(args: &Value) -> Result<i64, String> {
+    let reference = args.get("memoryReference").as_str().ok_or("missing memoryReference")?;
+    let addr = expr::parse_number(reference)? as i64;
+    addr.checked_add(args.get("offset").as_i64().unwrap_or(0)).ok_or_else(|| "offset out of range".to_string())
+}
+
+fn read_memory(chip: &Chip, args: &Value) -> Result<Value, String> {
+    let memory = chip.memory();
+    let addr = memory_reference(args)?.clamp(0, memory.len() as i64) as usize;
+    let count = args.get("count").as_i64().unwrap_or(0).max(0);
+    let end = (addr as i64).saturating_add(count).min(memory.len() as i64) as usize;
+
+    Ok(object(vec![
+        ("address", address(addr)),
+        ("data", base64::encode(&memory[addr .. end]).into()),
+        ("unreadableBytes", (count - (end - addr) as i64).into()),
+    ]))
+}
+
+fn disassemble(debugger: &Debugger, args: &Value) -> Result<Value, String> {
+    let memory = debugger.chip().memory();
+    let labels = debugger.labels();
+    let addr = memory_reference(args)?;
+    let start = args
+        .get("instructionOffset")
+        .as_i64()
+        .unwrap_or(0)
+        .checked_mul(2)
+        .and_then(|offset| addr.checked_add(offset))
+        .ok_or("instruction offset out of range")?;
+    // No more instructions than there are bytes of memory.
+    let count = args.get("instructionCount").as_i64().unwrap_or(0).clamp(0, memory.len() as i64);
+
+    let instructions: Vec<Value> = (0 .. count)
+        .map(|n| start.saturating_add(2 * n))
+        .map(|addr| {
+            if addr < 0 || addr as usize + 1 >= memory.len() {
+                return object(vec![
+                    ("address", format!("0x{:04x}", addr.max(0)).into()),
+                    ("instruction", "".into()),
+                    ("presentationHint", "invalid".into()),
+                ]);
+            }
+
+            let i = disasm::instruction(memory, addr as usize);
+            let mut fields = vec![
+                ("address", address(addr as usize)),
+                ("instructionBytes", format!("{i:04x}").into()),
+                ("instruction", disasm::mnemonic(&decode::decode(i), &labels).into()),
+            ];
+            if let Some(label) = labels.get(&(addr as Address)) {
+                fields.push(("symbol", label.as_str().into()));
+            }
+            object(fields)
+        })
+        .collect();
+
+    Ok(object(vec![("instructions", instructions.into())]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // 0200 LD V0, 1; CALL 0208; ADD V0, 1; JP 0206
+    // 0208 ADD V0, 0x10; RET
+    const ROM: [u8; 12] = [0x60, 0x01, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06, 0x70, 0x10, 0x00, 0xEE];
+
+    const SYMBOLS: &str = "0x200 main\n0x200 game.8o:1\n0x202 game.8o:2\n0x204 game.8o:4\n0x208 sub\n0x208 game.8o:7\n";
+
+    fn request(command: &str, arguments: &str) -> String {
+        let body = format!("{{\"seq\":1,\"type\":\"request\",\"command\":\"{command}\",\"arguments\":{arguments}}}");
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    // Launches the ROM with its symbols, then sends the requests through
+    // handle, running in between as serve does. Returns what was sent back.
+    fn script(stop_on_entry: bool, requests: &[String]) -> Vec<Value> {
+        // Tests run at once, so each script has its own files.
+        static SCRIPTS: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("nn-dap-{}-{}", std::process::id(), SCRIPTS.fetch_add(1, Ordering::Relaxed));
+        let rom = std::env::temp_dir().join(format!("{name}.ch8"));
+        let symbols = std::env::temp_dir().join(format!("{name}.sym"));
+        std::fs::write(&rom, ROM).unwrap();
+        std::fs::write(&symbols, SYMBOLS).unwrap();
+
+        let launch = format!(
+            "{{\"program\":\"{}\",\"symbols\":\"{}\",\"stopOnEntry\":{stop_on_entry}}}",
+            rom.display(),
+            symbols.display(),
+        );
+        let mut input = [request("initialize", "{}"), request("launch", &launch)].concat();
+        input.extend(requests.iter().cloned());
+
+        let mut adapter = Adapter {
+            out: Vec::new(),
+            seq: 1,
+            window: WindowSettings::default(),
+            headless: true,
+            palette: Palette::default(),
+            symbols_path: None,
+            debugger: None,
+            stop_on_entry: false,
+            breakpoints: HashSet::new(),
+            source_breakpoints: HashMap::new(),
+            state: State::Stopped,
+        };
+
+        let mut input = Cursor::new(input);
+        while let Some(message) = read_message(&mut input) {
+            if !adapter.handle(&message) {
+                break;
+            }
+            while let State::Running(_) = adapter.state {
+                if !adapter.run() {
+                    break;
+                }
+            }
+        }
+        std::fs::remove_file(rom).unwrap();
+        std::fs::remove_file(symbols).unwrap();
+
+        let mut output = Cursor::new(adapter.out);
+        std::iter::from_fn(|| read_message(&mut output)).collect()
+    }
+
+    // The body of the last response to command, which must have succeeded.
+    fn body<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        let response = messages.iter().rev().find(|m| m.get("type").as_str() == Some("response") && m.get("command").as_str() == Some(command)).unwrap();
+        assert_eq!(response.get("success").as_bool(), Some(true), "{command}");
+        response.get("body")
+    }
+
+    fn stops(messages: &[Value]) -> Vec<&str> {
+        messages.iter().filter(|m| m.get("event").as_str() == Some("stopped")).filter_map(|m| m.get("body").get("reason").as_str()).collect()
+    }
+
+    fn top_frame(messages: &[Value]) -> &Value {
+        &body(messages, "stackTrace").get("stackFrames").as_array()[0]
+    }
+
+    #[test]
+    fn initialize_and_launch() {
+        let messages = script(true, &[request("configurationDone", "{}"), request("stackTrace", "{}")]);
+
+        assert_eq!(body(&messages, "initialize").get("supportsDisassembleRequest").as_bool(), Some(true));
+        assert!(messages.iter().any(|m| m.get("event").as_str() == Some("initialized")));
+        assert_eq!(stops(&messages), ["entry"]);
+
+        let frame = top_frame(&messages);
+        assert_eq!(frame.get("name").as_str(), Some("main"));
+        assert_eq!(frame.get("line").as_i64(), Some(1));
+        assert_eq!(frame.get("instructionPointerReference").as_str(), Some("0x0200"));
+    }
+
+    #[test]
+    fn source_breakpoints() {
+        let messages = script(false, &[
+            request("setBreakpoints", "{\"source\":{\"path\":\"/src/game.8o\"},\"breakpoints\":[{\"line\":5},{\"line\":20}]}"),
+            request("configurationDone", "{}"),
+            request("stackTrace", "{}"),
+        ]);
+
+        let breakpoints = body(&messages, "setBreakpoints").get("breakpoints").as_array();
+        assert_eq!(breakpoints[0].get("verified").as_bool(), Some(true));
+        assert_eq!(breakpoints[0].get("line").as_i64(), Some(7));
+        assert_eq!(breakpoints[0].get("instructionReference").as_str(), Some("0x0208"));
+        assert_eq!(breakpoints[1].get("verified").as_bool(), Some(false));
+
+        assert_eq!(stops(&messages), ["breakpoint"]);
+        assert_eq!(top_frame(&messages).get("name").as_str(), Some("sub"));
+        assert_eq!(body(&messages, "stackTrace").get("totalFrames").as_i64(), Some(2));
+    }
+
+    #[test]
+    fn next_steps_over_calls() {
+        let messages = script(true, &[
+            request("configurationDone", "{}"),
+            request("next", "{\"threadId\":1}"),
+            request("next", "{\"threadId\":1}"),
+            request("stackTrace", "{}"),
+            request("evaluate", "{\"expression\":\"v0\"}"),
+        ]);
+
+        assert_eq!(stops(&messages), ["entry", "step", "step"]);
+        assert_eq!(top_frame(&messages).get("instructionPointerReference").as_str(), Some("0x0204"));
+        assert_eq!(body(&messages, "evaluate").get("result").as_str(), Some("17 (0x11)"));
+    }
+
+    #[test]
+    fn read_memory() {
+        let messages = script(true, &[request("readMemory", "{\"memoryReference\":\"0x200\",\"count\":4}")]);
+        let memory = body(&messages, "readMemory");
+        assert_eq!(memory.get("data").as_str(), Some("YAEiCA=="));
+        assert_eq!(memory.get("unreadableBytes").as_i64(), Some(0));
+
+        let messages = script(true, &[request("readMemory", "{\"memoryReference\":\"0xffe\",\"count\":1000000}")]);
+        let memory = body(&messages, "readMemory");
+        assert_eq!(memory.get("address").as_str(), Some("0x0ffe"));
+        assert_eq!(memory.get("unreadableBytes").as_i64(), Some(1000000 - 2));
+
+        let messages = script(true, &[request("readMemory", "{\"memoryReference\":\"0xffe\",\"count\":1e19}")]);
+        assert_eq!(body(&messages, "readMemory").get("data").as_str(), Some("AAA="));
+
+        let messages = script(true, &[request("readMemory", "{\"memoryReference\":\"0x200\",\"offset\":9223372036854775807,\"count\":1}")]);
+        assert_eq!(messages.last().unwrap().get("success").as_bool(), Some(false));
+    }
+
+    #[test]
+    fn disassemble() {
+        let messages = script(true, &[request("disassemble", "{\"memoryReference\":\"0x200\",\"instructionOffset\":1,\"instructionCount\":2}")]);
+        let instructions = body(&messages, "disassemble").get("instructions").as_array();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].get("address").as_str(), Some("0x0202"));
+        assert_eq!(instructions[0].get("instructionBytes").as_str(), Some("2208"));
+        assert_eq!(instructions[1].get("instructionBytes").as_str(), Some("7001"));
+
+        let messages = script(true, &[request("disassemble", "{\"memoryReference\":\"0x200\",\"instructionCount\":9223372036854775807}")]);
+        let instructions = body(&messages, "disassemble").get("instructions").as_array();
+        assert_eq!(instructions.len(), 4096);
+        assert_eq!(instructions[4095].get("presentationHint").as_str(), Some("invalid"));
+
+        let messages = script(true, &[request("disassemble", "{\"memoryReference\":\"0x200\",\"instructionOffset\":9223372036854775807,\"instructionCount\":1}")]);
+        assert_eq!(messages.last().unwrap().get("success").as_bool(), Some(false));
+    }
+
+    #[test]
+    fn oversized_messages_end_the_session() {
+        let mut input = Cursor::new(format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE_SIZE + 1));
+        assert_eq!(read_message(&mut input), None);
+
+        let mut input = Cursor::new(request("threads", "{}"));
+        assert_eq!(read_message(&mut input).unwrap().get("command").as_str(), Some("threads"));
+    }
+}
@@ -1,44 +1,286 @@
-use std::io::{self, Write};
+use crate::chip::{Chip, IMAGE_SCALE};
+use crate::command::{self, Command};
+use crate::debugger::{Debugger, ReverseStop, Stop};
+use crate::disasm;
+use crate::expr::Expr;
+use crate::get_line::LineReader;
+use crate::symbols::Symbols;
+use crate::text_screen::{self, Style};
+use crate::types::*;
 
-use crate::chip::Chip;
-use crate::get_line::get_line;
+// The command line front end to the debugger.
+
+pub fn debug(chip: Chip, script: Option<String>, symbols: Symbols) {
+    let mut input = match script {
+        Some(path) => match LineReader::script(&path) {
+            Ok(input) => input,
+            Err(err) => { println!("{path}: {err}"); return; }
+        },
+        None => LineReader::interactive(),
+    };
 
-pub fn debug(rom: Vec<u8>) {
     println!("Debug mode (h for help)");
 
-    let mut chip = Chip::new();
-
-    chip.load_rom(rom);
-    chip.dump_next_instruction();
-    prompt();
-
-    while let Some(line) = get_line() {
-        match line.trim_start().chars().next() {
-            Some('.') => chip.dump_next_instruction(),
-            Some('r') => chip.dump_registers(),
-            Some('m') => chip.dump_memory(),
-            Some('c') => chip.dump_stack(),
-            Some('s') => { chip.step(); chip.dump_next_instruction(); }
-            Some('h') => help(),
-            Some('q') => break,
-            Some(_) => println!("Unknown command."),
-            None => (),
+    let mut debugger = Debugger::new(chip, symbols);
+    let mut last = String::new();
+
+    list(&debugger, None).unwrap();
+
+    while let Some(line) = input.get_line("> ") {
+        let line = if line.trim().is_empty() { last.clone() } else { line };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match command::parse(&line) {
+            Ok(Command::Quit) => break,
+            Ok(cmd) => {
+                last = if repeats(&cmd) { line } else { String::new() };
+                exec(&mut debugger, cmd)
+            },
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            println!("{err}");
+        }
+    }
+}
+
+// Whether an empty line runs the command again: only stepping and looking
+// at memory or code, never changing anything.
+fn repeats(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::Step(_) | Command::StepLine | Command::Next | Command::ReverseStep(_) | Command::Memory(_) | Command::List(_)
+    )
+}
+
+fn exec(debugger: &mut Debugger, cmd: Command) -> Result<(), String> {
+    match cmd {
+        Command::Instruction => debugger.chip().dump_next_instruction(),
+        Command::List(addr) => list(debugger, addr)?,
+        Command::Registers => debugger.chip().dump_registers(),
+        Command::Memory(range) => memory(debugger, range)?,
+        Command::Stack => stack(debugger),
+        Command::Screen(style, changes) => screen(debugger, style, changes),
+        Command::Step(n) => {
+            let n = optional(debugger, n, 1)?;
+            let stop = debugger.step(n);
+            stopped(debugger, stop)?;
+        },
+        Command::StepLine => {
+            let stop = debugger.step_line()?;
+            stopped(debugger, stop)?;
+        },
+        Command::Next => {
+            let stop = debugger.next();
+            stopped(debugger, stop)?;
+        },
+        Command::Finish => {
+            let stop = debugger.finish()?;
+            stopped(debugger, stop)?;
+        },
+        Command::Continue => {
+            let stop = debugger.go();
+            stopped(debugger, stop)?;
+        },
+        Command::ReverseStep(n) => {
+            let n = optional(debugger, n, 1)?;
+            let stop = debugger.reverse_step(n)?;
+            reverse_stopped(debugger, stop)?;
+        },
+        Command::ReverseContinue => {
+            let stop = debugger.reverse_continue()?;
+            reverse_stopped(debugger, stop)?;
+        },
+        Command::Record(size) => record(debugger, size)?,
+        Command::Break(None) => list_breakpoints(debugger),
+        Command::Break(Some((addr, cond))) => {
+            let addr = debugger.eval_address(&addr)?;
+            debugger.set_breakpoint(addr, cond);
+            println!("Breakpoint set at {}", debugger.describe(addr));
+        },
+        Command::Delete(addr) => {
+            let addr = debugger.eval_address(&addr)?;
+            debugger.delete_breakpoint(addr)?;
+        },
+        Command::Set(var, value) => {
+            let value = debugger.eval(&value)?;
+            debugger.set(var, value)?;
+        },
+        Command::Write(addr, bytes) => {
+            let addr = debugger.eval_address(&addr)? as usize;
+            debugger.write_memory(addr, &bytes)?;
+        },
+        Command::Fill(addr, len, byte) => {
+            let addr = debugger.eval_address(&addr)? as usize;
+            let len = debugger.eval(&len)? as usize;
+            debugger.fill_memory(addr, len, byte)?;
+        },
+        Command::Push(addr) => {
+            let addr = debugger.eval_address(&addr)?;
+            debugger.push_stack(addr);
+        },
+        Command::Pop => match debugger.pop_stack() {
+            Some(addr) => println!("{addr:04x}"),
+            None => println!("Call stack is empty"),
+        },
+        Command::Screenshot(path, scale) => {
+            let path = path.unwrap_or_else(|| debugger.chip().screenshot_path());
+            let scale = optional(debugger, scale, IMAGE_SCALE as u32)? as usize;
+            if !(1 ..= 64).contains(&scale) {
+                return Err("scale must be from 1 to 64".to_string());
+            }
+            debugger.chip().screenshot().scaled(scale).save(&path)?;
+            println!("Saved screenshot to {path}");
+        },
+        Command::Save(path) => {
+            let path = path.unwrap_or_else(|| debugger.chip().state_path());
+            debugger.chip().state().save(&path)?;
+            println!("Saved state to {path}");
+        },
+        Command::Load(path) => {
+            let path = path.unwrap_or_else(|| debugger.chip().state_path());
+            debugger.load_state(&path)?;
+            println!("Loaded state from {path}");
+            list(debugger, None)?;
+        },
+        Command::Help => command::help(),
+        Command::Quit => (),
+    }
+
+    Ok(())
+}
+
+fn optional(debugger: &Debugger, e: Option<Expr>, default: u32) -> Result<u32, String> {
+    match e {
+        Some(e) => debugger.eval(&e),
+        None => Ok(default),
+    }
+}
+
+fn stopped(debugger: &Debugger, stop: Stop) -> Result<(), String> {
+    match stop {
+        Stop::Done => (),
+        Stop::Breakpoint(addr, hits) => println!("Breakpoint at {} (hits: {hits})", debugger.describe(addr)),
+        Stop::ConditionError(addr, err) => println!("Breakpoint at {addr:04x}: {err}"),
+        Stop::Interrupted(addr) => println!("Interrupted at {}", debugger.describe(addr)),
+        Stop::Illegal(addr, i) => println!("Illegal instruction {i:04x} at {}", debugger.describe(addr)),
+        Stop::Closed => {
+            println!("Window closed");
+            return Ok(());
+        },
+    }
+
+    list(debugger, None)
+}
+
+fn reverse_stopped(debugger: &Debugger, stop: ReverseStop) -> Result<(), String> {
+    match stop {
+        ReverseStop::Done => (),
+        ReverseStop::Breakpoint(addr) => println!("Breakpoint at {}", debugger.describe(addr)),
+        ReverseStop::StartOfHistory => println!("Reached the start of the recorded history"),
+    }
+
+    list(debugger, None)
+}
+
+fn record(debugger: &mut Debugger, size: Option<Expr>) -> Result<(), String> {
+    if let Some(size) = size {
+        let size = debugger.eval(&size)? as usize;
+        debugger.resize_history(size);
+    }
+
+    let history = debugger.history();
+    println!("Recording {} of up to {} steps", history.len(), history.size());
+    Ok(())
+}
+
+fn list_breakpoints(debugger: &Debugger) {
+    let breakpoints = debugger.breakpoints();
+
+    if breakpoints.is_empty() {
+        println!("No breakpoints");
+        return;
+    }
+
+    for bp in breakpoints.iter() {
+        match &bp.cond {
+            Some((s, _)) => println!("{:04x} if {} (hits: {})", bp.addr, s, bp.hits),
+            None => println!("{:04x} (hits: {})", bp.addr, bp.hits),
+        }
+    }
+}
+
+fn list(debugger: &Debugger, addr: Option<Expr>) -> Result<(), String> {
+    let chip = debugger.chip();
+    let symbols = debugger.symbols();
+    let addr = match addr {
+        Some(addr) => debugger.eval_address(&addr)?,
+        None => chip.pc(),
+    };
+
+    let addrs: Vec<Address> = debugger.breakpoints().iter().map(|bp| bp.addr).collect();
+
+    if let Some(location) = symbols.location(addr) {
+        match symbols.source(location) {
+            Some(source) => println!("{location}: {}", source.trim()),
+            None => println!("{location}"),
+        }
+    }
+
+    disasm::list(chip.memory(), addr, chip.pc(), &addrs, &debugger.labels(), 4, 8);
+    Ok(())
+}
+
+fn stack(debugger: &Debugger) {
+    let stack = debugger.chip().stack();
+
+    if stack.is_empty() {
+        println!("Call stack is empty");
+        return;
+    }
+
+    println!("Call stack:");
+    for addr in stack.iter().rev() {
+        match debugger.symbols().location(*addr) {
+            Some(location) => println!("{} at {location}", debugger.describe(*addr)),
+            None => println!("{}", debugger.describe(*addr)),
         }
-        prompt();
     }
 }
 
-fn prompt() {
-    print!("> ");
-    io::stdout().flush().unwrap();
+fn screen(debugger: &Debugger, style: Style, changes: bool) {
+    let chip = debugger.chip();
+    let (width, height) = chip.display_size();
+    let mut changed = vec![false; width * height];
+
+    if changes {
+        match debugger.history().last() {
+            Some(undo) => undo.display.iter().for_each(|(n, _)| changed[*n] = true),
+            None => println!("No recorded step to show changes for"),
+        }
+    }
+
+    let rows = text_screen::render(width, height, style, |x, y| chip.pixel(x, y), |x, y| changed[y * width + x]);
+    let border = "-".repeat(width / style.cell_size().0);
+
+    println!("+{border}+");
+    for row in rows {
+        println!("|{row}|");
+    }
+    println!("+{border}+");
 }
 
-fn help() {
-    println!(". - dump next instruction");
-    println!("r - dump registers");
-    println!("m - dump memory");
-    println!("c - dump call stack");
-    println!("s - step program by one instruction");
-    println!("h - help");
-    println!("q - quit");
+fn memory(debugger: &Debugger, range: Option<(Expr, Option<Expr>)>) -> Result<(), String> {
+    let (addr, len) = match range {
+        None => (0, debugger.chip().memory().len()),
+        Some((addr, None)) => (debugger.eval_address(&addr)? as usize, 64),
+        Some((addr, Some(len))) => (debugger.eval_address(&addr)? as usize, debugger.eval(&len)? as usize),
+    };
+
+    debugger.chip().dump_memory(addr, len);
+    Ok(())
 }
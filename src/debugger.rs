@@ -0,0 +1,562 @@
+use crate::chip::{Chip, PROGRAM_MEMORY_OFFSET};
+use crate::command::Condition;
+use crate::decode::{self, Decoded};
+use crate::disasm::{self, Labels};
+use crate::expr::{Context, Expr, Var};
+use crate::history::{History, DEFAULT_HISTORY_SIZE};
+use crate::state::State;
+use crate::symbols::{Location, Symbols};
+use crate::types::*;
+
+// Debugger state and operations, shared by the command line, gdb and debug
+// adapter front ends. Results are returned for the caller to show; only the
+// window's hotkeys, handled while running, print, and they can be turned off
+// for front ends that own stdout.
+
+pub struct Breakpoint {
+    pub addr: Address,
+    pub cond: Option<Condition>,
+    pub hits: u32,
+}
+
+// Why running forwards stopped.
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Done,
+    Breakpoint(Address, u32),
+    ConditionError(Address, String),
+    Interrupted(Address),
+    // The next instruction is illegal, and was not run.
+    Illegal(Address, Instruction),
+    Closed,
+}
+
+// Where running forwards stops, besides at breakpoints.
+pub enum Goal {
+    Steps(u32),
+    // The start of a source line other than this one.
+    Line(Option<Location>),
+    // The return from a call: the address after it, and the stack depth.
+    Return(Address, usize),
+    // Leaving the subroutine at this stack depth.
+    Depth(usize),
+    Never,
+}
+
+// Why running backwards stopped.
+#[derive(Debug, PartialEq)]
+pub enum ReverseStop {
+    Done,
+    Breakpoint(Address),
+    StartOfHistory,
+}
+
+pub struct Debugger {
+    chip: Chip,
+    symbols: Symbols,
+    breakpoints: Vec<Breakpoint>,
+    history: History,
+    hotkeys: bool,
+}
+
+impl Debugger {
+    pub fn new(chip: Chip, symbols: Symbols) -> Self {
+        Self {
+            chip,
+            symbols,
+            breakpoints: Vec::new(),
+            history: History::new(DEFAULT_HISTORY_SIZE),
+            hotkeys: true,
+        }
+    }
+
+    // Stops the window's hotkeys being handled while running.
+    pub fn disable_hotkeys(&mut self) {
+        self.hotkeys = false;
+    }
+
+    pub fn chip(&self) -> &Chip {
+        &self.chip
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn eval(&self, e: &Expr) -> Result<u32, String> {
+        e.eval(&Context { chip: &self.chip, hits: 0, symbols: &self.symbols })
+    }
+
+    pub fn eval_address(&self, e: &Expr) -> Result<Address, String> {
+        let addr = self.eval(e)?;
+        if addr as usize >= self.chip.memory().len() {
+            return Err(format!("address out of range: {addr:04x}"));
+        }
+        Ok(addr as Address)
+    }
+
+    pub fn labels(&self) -> Labels {
+        labels(self.chip.memory(), &self.symbols)
+    }
+
+    // Names addr by the nearest symbol, if any, e.g. "0236 <draw_player+2>".
+    pub fn describe(&self, addr: Address) -> String {
+        match self.symbols.describe(addr) {
+            Some(name) => format!("{addr:04x} <{name}>"),
+            None => format!("{addr:04x}"),
+        }
+    }
+}
+
+// Breakpoints.
+impl Debugger {
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn set_breakpoint(&mut self, addr: Address, cond: Option<Condition>) {
+        self.breakpoints.retain(|bp| bp.addr != addr);
+        self.breakpoints.push(Breakpoint { addr, cond, hits: 0 });
+    }
+
+    pub fn delete_breakpoint(&mut self, addr: Address) -> Result<(), String> {
+        let len = self.breakpoints.len();
+
+        self.breakpoints.retain(|bp| bp.addr != addr);
+        if self.breakpoints.len() == len {
+            return Err(format!("No breakpoint at {addr:04x}"));
+        }
+
+        Ok(())
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    // Counts a hit on any breakpoint at the PC and checks its condition.
+    fn hit_breakpoint(&mut self) -> Option<Stop> {
+        let pc = self.chip.pc();
+
+        for bp in self.breakpoints.iter_mut().filter(|bp| bp.addr == pc) {
+            bp.hits += 1;
+
+            let result = match &bp.cond {
+                Some((_, cond)) => cond.eval(&Context { chip: &self.chip, hits: bp.hits, symbols: &self.symbols }),
+                None => Ok(1),
+            };
+
+            match result {
+                Ok(0) => (),
+                Ok(_) => return Some(Stop::Breakpoint(pc, bp.hits)),
+                Err(err) => return Some(Stop::ConditionError(pc, err)),
+            }
+        }
+
+        None
+    }
+
+    // Like hit_breakpoint, but without counting the hit.
+    fn at_breakpoint(&self) -> bool {
+        let pc = self.chip.pc();
+
+        self.breakpoints.iter().filter(|bp| bp.addr == pc).any(|bp| match &bp.cond {
+            Some((_, cond)) => cond.eval(&Context { chip: &self.chip, hits: bp.hits, symbols: &self.symbols }).unwrap_or(1) != 0,
+            None => true,
+        })
+    }
+}
+
+// Running forwards.
+impl Debugger {
+    pub fn step(&mut self, n: u32) -> Stop {
+        if n == 0 {
+            return Stop::Done;
+        }
+
+        self.run(Goal::Steps(n))
+    }
+
+    pub fn step_line(&mut self) -> Result<Stop, String> {
+        if !self.symbols.has_lines() {
+            return Err("No line information loaded".to_string());
+        }
+
+        let start = self.symbols.location(self.chip.pc()).cloned();
+        Ok(self.run(Goal::Line(start)))
+    }
+
+    pub fn next(&mut self) -> Stop {
+        self.run(self.next_goal())
+    }
+
+    pub fn finish(&mut self) -> Result<Stop, String> {
+        let goal = self.finish_goal()?;
+        Ok(self.run(goal))
+    }
+
+    pub fn go(&mut self) -> Stop {
+        self.run(Goal::Never)
+    }
+
+    // Over a call to its return, or else a single step.
+    pub fn next_goal(&self) -> Goal {
+        match decode::decode(self.chip.next_instruction()) {
+            Decoded::Call(_) => Goal::Return(self.chip.pc() + 2, self.chip.stack_depth()),
+            _ => Goal::Steps(1),
+        }
+    }
+
+    pub fn finish_goal(&self) -> Result<Goal, String> {
+        match self.chip.stack_depth() {
+            0 => Err("Not in a subroutine".to_string()),
+            depth => Ok(Goal::Depth(depth)),
+        }
+    }
+
+    pub fn run(&mut self, mut goal: Goal) -> Stop {
+        loop {
+            if let Some(stop) = self.run_for(&mut goal, usize::MAX) {
+                return stop;
+            }
+        }
+    }
+
+    // Steps at least once, then until the goal is reached, a breakpoint is
+    // hit, a break is requested or the window is closed, or else returns
+    // None after limit steps, for front ends that check for requests while
+    // running. The goal keeps any steps left, to carry on with.
+    pub fn run_for(&mut self, goal: &mut Goal, limit: usize) -> Option<Stop> {
+        let stop = self.run_steps(goal, limit);
+        if stop.is_some() {
+            self.chip.suspend();
+        }
+        stop
+    }
+
+    fn run_steps(&mut self, goal: &mut Goal, limit: usize) -> Option<Stop> {
+        for _ in 0 .. limit {
+            if let Decoded::Illegal(i) = decode::decode(self.chip.next_instruction()) {
+                return Some(Stop::Illegal(self.chip.pc(), i));
+            }
+            self.history.push(self.chip.step_undoable());
+
+            if !self.chip.is_open() {
+                return Some(Stop::Closed);
+            }
+            if self.reached(goal) {
+                return Some(Stop::Done);
+            }
+            if let Some(stop) = self.hit_breakpoint() {
+                return Some(stop);
+            }
+            if self.chip.break_requested() {
+                return Some(Stop::Interrupted(self.chip.pc()));
+            }
+            if self.hotkeys && self.chip.handle_hotkeys() {
+                self.history.clear();
+            }
+        }
+
+        None
+    }
+
+    fn reached(&self, goal: &mut Goal) -> bool {
+        let pc = self.chip.pc();
+
+        match goal {
+            Goal::Steps(n) => {
+                *n -= 1;
+                *n == 0
+            },
+            Goal::Line(start) => self.symbols.line_start(pc).is_some_and(|location| Some(location) != start.as_ref()),
+            Goal::Return(addr, depth) => pc == *addr && self.chip.stack_depth() == *depth,
+            Goal::Depth(depth) => self.chip.stack_depth() < *depth,
+            Goal::Never => false,
+        }
+    }
+}
+
+// Running backwards.
+impl Debugger {
+    pub fn reverse_step(&mut self, n: u32) -> Result<ReverseStop, String> {
+        self.reverse_until(n as usize, false)
+    }
+
+    pub fn reverse_continue(&mut self) -> Result<ReverseStop, String> {
+        self.reverse_until(usize::MAX, true)
+    }
+
+    // Undoes up to n steps, stopping early at a breakpoint if asked.
+    fn reverse_until(&mut self, n: usize, breakpoints: bool) -> Result<ReverseStop, String> {
+        let mut undone = 0;
+        let mut stop = ReverseStop::Done;
+
+        for _ in 0 .. n {
+            match self.history.pop() {
+                Some(undo) => {
+                    self.chip.undo(&undo);
+                    undone += 1;
+                },
+                None => break,
+            }
+            if breakpoints && self.at_breakpoint() {
+                stop = ReverseStop::Breakpoint(self.chip.pc());
+                break;
+            }
+        }
+
+        if undone == 0 {
+            return Err("No recorded history to go back through".to_string());
+        }
+        self.chip.refresh();
+
+        if self.history.len() == 0 {
+            stop = ReverseStop::StartOfHistory;
+        }
+
+        Ok(stop)
+    }
+
+    pub fn resize_history(&mut self, size: usize) {
+        self.history.resize(size);
+    }
+}
+
+// State modification. Changing state by hand would make going back through
+// it misleading, so history is forgotten.
+impl Debugger {
+    pub fn set(&mut self, var: Var, value: u32) -> Result<(), String> {
+        match var {
+            Var::V(x) => self.chip.set_v(x, value as Byte),
+            Var::I => self.chip.set_i(value as Address),
+            Var::Pc => {
+                // The instruction at pc is two bytes and must be in memory.
+                if value > 0xFFFF || value as usize + 1 >= self.chip.memory().len() {
+                    return Err(format!("pc out of memory: {value:04x}"));
+                }
+                self.chip.set_pc(value as Address);
+            },
+            Var::Dt => self.chip.set_delay_timer(value as u8),
+            Var::St => self.chip.set_sound_timer(value as u8),
+            Var::Sp | Var::Hits => return Err("register is read-only".to_string()),
+        }
+
+        self.history.clear();
+        Ok(())
+    }
+
+    pub fn write_memory(&mut self, addr: usize, bytes: &[Byte]) -> Result<(), String> {
+        if addr + bytes.len() > self.chip.memory().len() {
+            return Err(format!("range out of memory: {addr:04x} + {}", bytes.len()));
+        }
+
+        self.chip.write_memory(addr, bytes);
+        self.history.clear();
+        Ok(())
+    }
+
+    pub fn fill_memory(&mut self, addr: usize, len: usize, byte: Byte) -> Result<(), String> {
+        if addr.checked_add(len).is_none_or(|end| end > self.chip.memory().len()) {
+            return Err(format!("range out of memory: {addr:04x} + {len}"));
+        }
+
+        self.chip.fill_memory(addr, len, byte);
+        self.history.clear();
+        Ok(())
+    }
+
+    pub fn push_stack(&mut self, addr: Address) {
+        self.chip.push_stack(addr);
+        self.history.clear();
+    }
+
+    pub fn pop_stack(&mut self) -> Option<Address> {
+        self.history.clear();
+        self.chip.pop_stack()
+    }
+
+    pub fn load_state(&mut self, path: &str) -> Result<(), String> {
+        self.chip.restore(&State::load(path)?)?;
+        self.history.clear();
+        Ok(())
+    }
+}
+
+// Generated labels, overridden by any loaded symbols.
+pub fn labels(memory: &[Byte], symbols: &Symbols) -> Labels {
+    let mut labels = disasm::labels(memory, PROGRAM_MEMORY_OFFSET);
+    labels.extend(symbols.labels().iter().map(|(addr, label)| (*addr, label.clone())));
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr;
+    use crate::frontend::Frontend;
+
+    // 0200 LD V0, 1; CALL 0208; ADD V0, 1; JP 0206
+    // 0208 ADD V0, 0x10; ADD V0, 0x20; RET
+    // 020e an illegal instruction
+    const ROM: [u8; 16] = [
+        0x60, 0x01, 0x22, 0x08, 0x70, 0x01, 0x12, 0x06,
+        0x70, 0x10, 0x70, 0x20, 0x00, 0xEE, 0xFF, 0xFF,
+    ];
+
+    fn debugger() -> Debugger {
+        let mut chip = Chip::new(Frontend::headless());
+        chip.load_font();
+        chip.load_rom(ROM.to_vec());
+        Debugger::new(chip, Symbols::default())
+    }
+
+    fn at(debugger: &Debugger) -> (Address, Byte, usize) {
+        let chip = debugger.chip();
+        (chip.pc(), chip.v(0), chip.stack_depth())
+    }
+
+    fn condition(s: &str) -> Option<Condition> {
+        Some((s.to_string(), expr::parse(s).unwrap()))
+    }
+
+    #[test]
+    fn step() {
+        let mut debugger = debugger();
+
+        assert_eq!(debugger.step(0), Stop::Done);
+        assert_eq!(at(&debugger), (0x200, 0, 0));
+        assert_eq!(debugger.step(2), Stop::Done);
+        assert_eq!(at(&debugger), (0x208, 1, 1));
+
+        debugger.set_breakpoint(0x20A, None);
+        assert_eq!(debugger.step(5), Stop::Breakpoint(0x20A, 1));
+        assert_eq!(at(&debugger), (0x20A, 0x11, 1));
+    }
+
+    #[test]
+    fn next() {
+        let mut debugger = debugger();
+
+        assert_eq!(debugger.next(), Stop::Done);
+        assert_eq!(at(&debugger), (0x202, 1, 0));
+        assert_eq!(debugger.next(), Stop::Done);
+        assert_eq!(at(&debugger), (0x204, 0x31, 0));
+
+        let mut debugger = self::debugger();
+        debugger.step(1);
+        debugger.set_breakpoint(0x20C, None);
+        assert_eq!(debugger.next(), Stop::Breakpoint(0x20C, 1));
+        assert_eq!(at(&debugger), (0x20C, 0x31, 1));
+    }
+
+    #[test]
+    fn finish() {
+        let mut debugger = debugger();
+
+        assert!(debugger.finish().is_err());
+        debugger.step(2);
+        assert_eq!(debugger.finish(), Ok(Stop::Done));
+        assert_eq!(at(&debugger), (0x204, 0x31, 0));
+
+        let mut debugger = self::debugger();
+        debugger.step(2);
+        debugger.set_breakpoint(0x20A, None);
+        assert_eq!(debugger.finish(), Ok(Stop::Breakpoint(0x20A, 1)));
+    }
+
+    #[test]
+    fn go() {
+        let mut debugger = debugger();
+
+        debugger.set_breakpoint(0x204, None);
+        assert_eq!(debugger.go(), Stop::Breakpoint(0x204, 1));
+        assert_eq!(at(&debugger), (0x204, 0x31, 0));
+
+        // The loop at 0206 jumps to itself.
+        debugger.set_breakpoint(0x206, condition("hits == 3"));
+        assert_eq!(debugger.go(), Stop::Breakpoint(0x206, 3));
+        assert_eq!(debugger.breakpoints().iter().find(|bp| bp.addr == 0x206).unwrap().hits, 3);
+
+        debugger.set_breakpoint(0x206, condition("[0x1000]"));
+        assert_eq!(debugger.go(), Stop::ConditionError(0x206, "address out of range: 1000".to_string()));
+
+        debugger.delete_breakpoint(0x206).unwrap();
+        let mut goal = Goal::Never;
+        assert_eq!(debugger.run_for(&mut goal, 100), None);
+        assert_eq!(at(&debugger), (0x206, 0x32, 0));
+    }
+
+    #[test]
+    fn illegal_instructions_stop_before_running() {
+        let mut debugger = debugger();
+
+        debugger.set(Var::Pc, 0x20C).unwrap();
+        debugger.push_stack(0x20E);
+        assert_eq!(debugger.go(), Stop::Illegal(0x20E, 0xFFFF));
+        assert_eq!(debugger.go(), Stop::Illegal(0x20E, 0xFFFF));
+        assert_eq!(at(&debugger).0, 0x20E);
+    }
+
+    #[test]
+    fn reverse_step() {
+        let mut debugger = debugger();
+
+        assert!(debugger.reverse_step(1).is_err());
+        debugger.step(4);
+        assert_eq!(at(&debugger), (0x20C, 0x31, 1));
+
+        assert_eq!(debugger.reverse_step(1), Ok(ReverseStop::Done));
+        assert_eq!(at(&debugger), (0x20A, 0x11, 1));
+        assert_eq!(debugger.reverse_step(2), Ok(ReverseStop::Done));
+        assert_eq!(at(&debugger), (0x202, 1, 0));
+        assert_eq!(debugger.reverse_step(5), Ok(ReverseStop::StartOfHistory));
+        assert_eq!(at(&debugger), (0x200, 0, 0));
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut debugger = debugger();
+
+        debugger.set_breakpoint(0x204, None);
+        debugger.go();
+        debugger.set_breakpoint(0x208, None);
+        debugger.delete_breakpoint(0x204).unwrap();
+        debugger.step(3);
+        assert_eq!(at(&debugger), (0x206, 0x32, 0));
+
+        assert_eq!(debugger.reverse_continue(), Ok(ReverseStop::Breakpoint(0x208)));
+        assert_eq!(at(&debugger), (0x208, 1, 1));
+        assert_eq!(debugger.reverse_continue(), Ok(ReverseStop::StartOfHistory));
+        assert_eq!(at(&debugger), (0x200, 0, 0));
+    }
+
+    #[test]
+    fn pc_stays_in_memory() {
+        let mut debugger = debugger();
+
+        assert!(debugger.set(Var::Pc, 0x5000).is_err());
+        assert!(debugger.set(Var::Pc, 0x10200).is_err());
+        assert!(debugger.set(Var::Pc, 0xFFF).is_err());
+        assert_eq!(debugger.chip().pc(), 0x200);
+
+        debugger.set(Var::Pc, 0xFFE).unwrap();
+        assert_eq!(debugger.chip().pc(), 0xFFE);
+    }
+
+    #[test]
+    fn fill_stays_in_memory() {
+        let mut debugger = debugger();
+
+        assert!(debugger.fill_memory(0, 0xFFFFFFFF, 0).is_err());
+        assert!(debugger.fill_memory(usize::MAX, 2, 0).is_err());
+        assert!(debugger.fill_memory(0xFFF, 2, 0).is_err());
+
+        debugger.fill_memory(0x202, 4, 0xAB).unwrap();
+        assert_eq!(debugger.chip().memory()[0x200 .. 0x208], [0x60, 0x01, 0xAB, 0xAB, 0xAB, 0xAB, 0x12, 0x06]);
+    }
+}
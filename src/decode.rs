@@ -14,8 +14,10 @@ pub enum Decoded {
     Draw(Register, Register, Nibble),
     FontChar(Register),
     GetKey(Register),
+    HighRes,
     Jump(Address),
     Load(Register),
+    LowRes,
     Move(Register, Byte),
     MoveIndex(Address),
     MoveXY(Register, Register),
@@ -88,6 +90,8 @@ fn a0(i: Instruction) -> Decoded {
     match i {
         0x00E0 => Decoded::ClearScreen,
         0x00EE => Decoded::Return,
+        0x00FE => Decoded::LowRes,
+        0x00FF => Decoded::HighRes,
         _ => Decoded::Illegal(i),
     }
 }
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use crate::decode::{self, Decoded};
+use crate::types::*;
+
+pub type Labels = HashMap<Address, String>;
+
+// Generates labels for every jump and call target found by decoding memory
+// from start onwards. Data is decoded too, so some labels may be spurious.
+pub fn labels(memory: &[Byte], start: usize) -> Labels {
+    let mut labels = Labels::new();
+
+    for addr in (start .. memory.len() - 1).step_by(2) {
+        match decode::decode(instruction(memory, addr)) {
+            Decoded::Call(nnn) => {
+                labels.insert(nnn, format!("sub_{nnn:04x}"));
+            },
+            Decoded::Jump(nnn) => {
+                labels.entry(nnn).or_insert_with(|| format!("L{nnn:04x}"));
+            },
+            _ => (),
+        }
+    }
+
+    labels
+}
+
+pub fn instruction(memory: &[Byte], addr: usize) -> Instruction {
+    (memory[addr] as Instruction) << 8 | memory[addr + 1] as Instruction
+}
+
+pub fn mnemonic(decoded: &Decoded, labels: &Labels) -> String {
+    let target = |nnn: &Address| match labels.get(nnn) {
+        Some(label) => label.clone(),
+        None => format!("0x{nnn:03x}"),
+    };
+
+    match decoded {
+        Decoded::Add(x, nn)              => format!("ADD V{x:X}, 0x{nn:02x}"),
+        Decoded::AddIndex(x)             => format!("ADD I, V{x:X}"),
+        Decoded::AddXY(x, y)             => format!("ADD V{x:X}, V{y:X}"),
+        Decoded::And(x, y)               => format!("AND V{x:X}, V{y:X}"),
+        Decoded::Call(nnn)               => format!("CALL {}", target(nnn)),
+        Decoded::ClearScreen             => "CLS".to_string(),
+        Decoded::Decimal(x)              => format!("LD B, V{x:X}"),
+        Decoded::DelayTimerGet(x)        => format!("LD V{x:X}, DT"),
+        Decoded::DelayTimerSet(x)        => format!("LD DT, V{x:X}"),
+        Decoded::Draw(x, y, n)           => format!("DRW V{x:X}, V{y:X}, {n}"),
+        Decoded::FontChar(x)             => format!("LD F, V{x:X}"),
+        Decoded::GetKey(x)               => format!("LD V{x:X}, K"),
+        Decoded::HighRes                 => "HIGH".to_string(),
+        Decoded::Jump(nnn)               => format!("JP {}", target(nnn)),
+        Decoded::Load(x)                 => format!("LD V{x:X}, [I]"),
+        Decoded::LowRes                  => "LOW".to_string(),
+        Decoded::Move(x, nn)             => format!("LD V{x:X}, 0x{nn:02x}"),
+        Decoded::MoveIndex(nnn)          => format!("LD I, 0x{nnn:03x}"),
+        Decoded::MoveXY(x, y)            => format!("LD V{x:X}, V{y:X}"),
+        Decoded::Or(x, y)                => format!("OR V{x:X}, V{y:X}"),
+        Decoded::Random(x, nn)           => format!("RND V{x:X}, 0x{nn:02x}"),
+        Decoded::Return                  => "RET".to_string(),
+        Decoded::SetSoundTimer(x)        => format!("LD ST, V{x:X}"),
+        Decoded::ShiftLeft(x, y)         => format!("SHL V{x:X}, V{y:X}"),
+        Decoded::ShiftRight(x, y)        => format!("SHR V{x:X}, V{y:X}"),
+        Decoded::SkipEqual(x, nn)        => format!("SE V{x:X}, 0x{nn:02x}"),
+        Decoded::SkipEqualXY(x, y)       => format!("SE V{x:X}, V{y:X}"),
+        Decoded::SkipKey(x)              => format!("SKP V{x:X}"),
+        Decoded::SkipNotEqual(x, nn)     => format!("SNE V{x:X}, 0x{nn:02x}"),
+        Decoded::SkipNotEqualXY(x, y)    => format!("SNE V{x:X}, V{y:X}"),
+        Decoded::SkipNotKey(x)           => format!("SKNP V{x:X}"),
+        Decoded::Store(x)                => format!("LD [I], V{x:X}"),
+        Decoded::SubXY(x, y)             => format!("SUB V{x:X}, V{y:X}"),
+        Decoded::SubYX(x, y)             => format!("SUBN V{x:X}, V{y:X}"),
+        Decoded::Xor(x, y)               => format!("XOR V{x:X}, V{y:X}"),
+
+        Decoded::Illegal(i)              => format!("DW 0x{i:04x}"),
+    }
+}
+
+// Lists instructions from before instructions ahead of addr to after
+// instructions past it, marking the PC with => and breakpoints with *.
+pub fn list(memory: &[Byte], addr: Address, pc: Address, breakpoints: &[Address], labels: &Labels, before: usize, after: usize) {
+    let addr = addr as usize;
+    let start = addr.saturating_sub(2 * before);
+    let end = usize::min(addr + 2 * (after + 1), memory.len() - 1);
+
+    for a in (start .. end).step_by(2) {
+        if let Some(label) = labels.get(&(a as Address)) {
+            println!("      {label}:");
+        }
+
+        let i = instruction(memory, a);
+        let pc_mark = if a == pc as usize { "=>" } else { "  " };
+        let bp_mark = if breakpoints.contains(&(a as Address)) { '*' } else { ' ' };
+
+        println!("{bp_mark}{pc_mark} {a:04x}: {i:04x}  {}", mnemonic(&decode::decode(i), labels));
+    }
+}
@@ -0,0 +1,418 @@
+use crate::chip::Chip;
+use crate::symbols::Symbols;
+use crate::types::*;
+
+// Expressions over machine state, used for breakpoint conditions.
+//
+//   b 0x2A4 if v0 == 5 && i > 0x300 && [i+2] != 0
+//
+// Numbers are decimal unless prefixed with 0x. Variables are v0 - vf, i, pc,
+// sp (call stack depth), dt, st and hits. Any other name is looked up as a
+// label in the loaded symbols. [e] reads the memory byte at e.
+// Operators and their precedence follow C.
+
+#[derive(Debug)]
+pub enum Expr {
+    Number(u32),
+    Var(Var),
+    Symbol(String),
+    Memory(Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Var {
+    V(Register),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    Hits,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+pub struct Context<'a> {
+    pub chip: &'a Chip,
+    pub hits: u32,
+    pub symbols: &'a Symbols,
+}
+
+pub fn parse(s: &str) -> Result<Expr, String> {
+    let tokens = tokenize(s)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr(0)?;
+
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {}", token)),
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, ctx: &Context) -> Result<u32, String> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Var(var) => Ok(var.eval(ctx)),
+            Expr::Symbol(name) => match ctx.symbols.address(name) {
+                Some(addr) => Ok(addr as u32),
+                None => Err(format!("unknown variable or label: {name}")),
+            },
+            Expr::Memory(addr) => {
+                let addr = addr.eval(ctx)? as usize;
+                match ctx.chip.memory().get(addr) {
+                    Some(byte) => Ok(*byte as u32),
+                    None => Err(format!("address out of range: {addr:04x}")),
+                }
+            },
+            Expr::Unary(op, e) => {
+                let a = e.eval(ctx)?;
+                Ok(match op {
+                    UnaryOp::Neg => a.wrapping_neg(),
+                    UnaryOp::Not => (a == 0) as u32,
+                    UnaryOp::BitNot => !a,
+                })
+            },
+            Expr::Binary(BinaryOp::And, a, b) => {
+                Ok((a.eval(ctx)? != 0 && b.eval(ctx)? != 0) as u32)
+            },
+            Expr::Binary(BinaryOp::Or, a, b) => {
+                Ok((a.eval(ctx)? != 0 || b.eval(ctx)? != 0) as u32)
+            },
+            Expr::Binary(op, a, b) => binary(*op, a.eval(ctx)?, b.eval(ctx)?),
+        }
+    }
+}
+
+impl Var {
+    fn eval(&self, ctx: &Context) -> u32 {
+        let chip = ctx.chip;
+        match self {
+            Var::V(x) => chip.v(*x) as u32,
+            Var::I => chip.i() as u32,
+            Var::Pc => chip.pc() as u32,
+            Var::Sp => chip.stack_depth() as u32,
+            Var::Dt => chip.delay_timer() as u32,
+            Var::St => chip.sound_timer() as u32,
+            Var::Hits => ctx.hits,
+        }
+    }
+}
+
+fn binary(op: BinaryOp, a: u32, b: u32) -> Result<u32, String> {
+    Ok(match op {
+        BinaryOp::Mul => a.wrapping_mul(b),
+        BinaryOp::Div => a.checked_div(b).ok_or("division by zero")?,
+        BinaryOp::Rem => a.checked_rem(b).ok_or("division by zero")?,
+        BinaryOp::Add => a.wrapping_add(b),
+        BinaryOp::Sub => a.wrapping_sub(b),
+        BinaryOp::Shl => a.checked_shl(b).unwrap_or(0),
+        BinaryOp::Shr => a.checked_shr(b).unwrap_or(0),
+        BinaryOp::Lt => (a < b) as u32,
+        BinaryOp::Le => (a <= b) as u32,
+        BinaryOp::Gt => (a > b) as u32,
+        BinaryOp::Ge => (a >= b) as u32,
+        BinaryOp::Eq => (a == b) as u32,
+        BinaryOp::Ne => (a != b) as u32,
+        BinaryOp::BitAnd => a & b,
+        BinaryOp::BitXor => a ^ b,
+        BinaryOp::BitOr => a | b,
+        // These short-circuit, so are evaluated in Expr::eval.
+        BinaryOp::And | BinaryOp::Or => unreachable!(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u32),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{n}"),
+            Token::Ident(s) => write!(f, "{s}"),
+            Token::Op(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+// Longest operators first, so that e.g. "<=" is not read as "<" then "=".
+const OPERATORS: [&str; 24] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<<", ">>",
+    "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "(", ")", "[", "]",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[.. len];
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(parse_number(word)?));
+            } else {
+                tokens.push(Token::Ident(word.to_string()));
+            }
+            rest = &rest[len ..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len() ..];
+        } else {
+            return Err(format!("unexpected character: {c}"));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+pub fn parse_number(s: &str) -> Result<u32, String> {
+    let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+
+    result.map_err(|_| format!("bad number: {s}"))
+}
+
+fn binary_op(token: &Token) -> Option<(BinaryOp, u8)> {
+    let op = match token {
+        Token::Op(op) => *op,
+        _ => return None,
+    };
+
+    Some(match op {
+        "*" => (BinaryOp::Mul, 10),
+        "/" => (BinaryOp::Div, 10),
+        "%" => (BinaryOp::Rem, 10),
+        "+" => (BinaryOp::Add, 9),
+        "-" => (BinaryOp::Sub, 9),
+        "<<" => (BinaryOp::Shl, 8),
+        ">>" => (BinaryOp::Shr, 8),
+        "<" => (BinaryOp::Lt, 7),
+        "<=" => (BinaryOp::Le, 7),
+        ">" => (BinaryOp::Gt, 7),
+        ">=" => (BinaryOp::Ge, 7),
+        "==" => (BinaryOp::Eq, 6),
+        "!=" => (BinaryOp::Ne, 6),
+        "&" => (BinaryOp::BitAnd, 5),
+        "^" => (BinaryOp::BitXor, 4),
+        "|" => (BinaryOp::BitOr, 3),
+        "&&" => (BinaryOp::And, 2),
+        "||" => (BinaryOp::Or, 1),
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or_else(|| "unexpected end of expression".to_string())
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.next()? {
+            Token::Op(o) if o == op => Ok(()),
+            token => Err(format!("expected {op}, found {token}")),
+        }
+    }
+
+    // Precedence climbing: only binary operators binding tighter than
+    // min_prec are folded into the left operand.
+    fn expr(&mut self, min_prec: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+
+        while let Some((op, prec)) = self.peek().and_then(binary_op) {
+            if prec <= min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(prec)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek() {
+            Some(Token::Op("-")) => UnaryOp::Neg,
+            Some(Token::Op("!")) => UnaryOp::Not,
+            Some(Token::Op("~")) => UnaryOp::BitNot,
+            _ => return self.primary(),
+        };
+
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Ident(name) => Ok(match var(&name) {
+                Ok(var) => Expr::Var(var),
+                Err(_) => Expr::Symbol(name),
+            }),
+            Token::Op("(") => {
+                let e = self.expr(0)?;
+                self.expect(")")?;
+                Ok(e)
+            },
+            Token::Op("[") => {
+                let e = self.expr(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(e)))
+            },
+            token => Err(format!("unexpected {token}")),
+        }
+    }
+}
+
+pub fn var(name: &str) -> Result<Var, String> {
+    match name.to_lowercase().as_str() {
+        "i" => Ok(Var::I),
+        "pc" => Ok(Var::Pc),
+        "sp" => Ok(Var::Sp),
+        "dt" => Ok(Var::Dt),
+        "st" => Ok(Var::St),
+        "hits" => Ok(Var::Hits),
+        name => match name.strip_prefix('v').map(|x| Register::from_str_radix(x, 16)) {
+            Some(Ok(x)) if x < 16 && name.len() == 2 => Ok(Var::V(x)),
+            _ => Err(format!("unknown variable: {name}")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::Frontend;
+
+    fn eval_with(s: &str, symbols: &Symbols) -> Result<u32, String> {
+        let mut chip = Chip::new(Frontend::headless());
+        chip.set_v(0, 5);
+        chip.set_v(0xF, 1);
+        chip.set_i(0x300);
+        chip.write_memory(0x302, &[0xAB]);
+        chip.push_stack(0x204);
+
+        parse(s)?.eval(&Context { chip: &chip, hits: 3, symbols })
+    }
+
+    fn eval(s: &str) -> Result<u32, String> {
+        eval_with(s, &Symbols::default())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval("1 | 2 ^ 3 & 6"), Ok(1));
+        assert_eq!(eval("1 + 1 == 2 && 3 < 2 || 4 >= 4"), Ok(1));
+        assert_eq!(eval("0 || 1 && 0"), Ok(0));
+        assert_eq!(eval("7 % 4 * 2"), Ok(6));
+    }
+
+    #[test]
+    fn unary() {
+        assert_eq!(eval("-1"), Ok(u32::MAX));
+        assert_eq!(eval("--3"), Ok(3));
+        assert_eq!(eval("!0"), Ok(1));
+        assert_eq!(eval("!5"), Ok(0));
+        assert_eq!(eval("~0"), Ok(u32::MAX));
+        assert_eq!(eval("-2 * 3"), Ok(6u32.wrapping_neg()));
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(eval("v0"), Ok(5));
+        assert_eq!(eval("VF"), Ok(1));
+        assert_eq!(eval("i"), Ok(0x300));
+        assert_eq!(eval("pc"), Ok(0x200));
+        assert_eq!(eval("sp"), Ok(1));
+        assert_eq!(eval("dt + st"), Ok(0));
+        assert_eq!(eval("hits"), Ok(3));
+        assert_eq!(eval("[i + 2]"), Ok(0xAB));
+        assert_eq!(eval("v0 == 5 && i > 0x2ff && [i+2] != 0"), Ok(1));
+    }
+
+    #[test]
+    fn symbols() {
+        let path = std::env::temp_dir().join(format!("nn-expr-{}.sym", std::process::id()));
+        std::fs::write(&path, "0x2A4 loop\n0x300 sprite\n").unwrap();
+        let symbols = Symbols::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(eval_with("loop", &symbols), Ok(0x2A4));
+        assert_eq!(eval_with("i == sprite", &symbols), Ok(1));
+        assert_eq!(eval_with("nowhere", &symbols), Err("unknown variable or label: nowhere".to_string()));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("").is_err());
+        assert!(parse("1 +").is_err());
+        assert!(parse("(1").is_err());
+        assert!(parse("[1").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("1 $ 2").is_err());
+        assert!(parse("0xZZ").is_err());
+        assert_eq!(eval("1 / 0"), Err("division by zero".to_string()));
+        assert_eq!(eval("1 % 0"), Err("division by zero".to_string()));
+        assert_eq!(eval("[0x1000]"), Err("address out of range: 1000".to_string()));
+        assert_eq!(eval("0 && 1 / 0"), Ok(0));
+        assert_eq!(eval("1 || [0x1000]"), Ok(1));
+    }
+}
@@ -0,0 +1,246 @@
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+
+use crate::palette::Palette;
+use crate::screen;
+use crate::terminal::Terminal;
+use crate::types::*;
+
+// Where the display goes and the keys come from.
+//
+// Window is a minifb window, which limits updates to 60 a second and so
+// paces the machine. The display is stretched to fill it, keeping its shape
+// with bars of background colour either side, and is laid out again each
+// frame at its current size, so a switch between low-res and hi-res keeps
+// the window's size. A fullscreen window has no close button, so Esc closes
+// it. Terminal draws the display as text instead. Headless has no display or
+// keys and runs as fast as it can, for playing back movies and capturing
+// clips without X.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    Break,
+    Save,
+    Load,
+    Screenshot,
+    Capture,
+    Rewind,
+}
+
+const DEFAULT_SCALE: usize = 8;
+
+#[derive(Clone, Copy)]
+pub enum WindowScale {
+    Times(usize),
+    // The largest whole scale that fits the screen, or the default scale if
+    // the screen cannot be found.
+    Fit,
+}
+
+#[derive(Clone, Copy)]
+pub struct WindowSettings {
+    pub scale: WindowScale,
+    // minifb has no fullscreen mode, so this is a borderless window covering
+    // the screen, or sized by scale if the screen cannot be found.
+    pub fullscreen: bool,
+    pub resizable: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self { scale: WindowScale::Times(DEFAULT_SCALE), fullscreen: false, resizable: false }
+    }
+}
+
+impl WindowScale {
+    pub fn parse(scale: &str) -> Result<Self, String> {
+        match scale {
+            "fit" => Ok(WindowScale::Fit),
+            _ => match scale.parse() {
+                Ok(scale @ 1 ..= 32) => Ok(WindowScale::Times(scale)),
+                _ => Err(format!("scale must be from 1 to 32 or fit: {scale}")),
+            },
+        }
+    }
+}
+
+pub enum Frontend {
+    Window { window: Box<Window>, fullscreen: bool },
+    Terminal(Box<Terminal>),
+    Headless { open: bool },
+}
+
+impl Frontend {
+    pub fn window(width: usize, height: usize, settings: WindowSettings) -> Self {
+        let fits = settings.fullscreen || matches!(settings.scale, WindowScale::Fit);
+        let screen = if fits { screen::primary() } else { None };
+
+        // minifb only scales by powers of two, so windows are sized here and
+        // the display is stretched into them.
+        let (width, height) = match (&screen, settings.scale) {
+            (Some(screen), _) if settings.fullscreen => (screen.width, screen.height),
+            (Some(screen), WindowScale::Fit) => {
+                let n = (screen.width / width).min(screen.height / height).max(1);
+                (width * n, height * n)
+            },
+            (None, WindowScale::Fit) => (width * DEFAULT_SCALE, height * DEFAULT_SCALE),
+            (_, WindowScale::Times(n)) => (width * n, height * n),
+        };
+
+        let window_options = WindowOptions {
+            borderless: settings.fullscreen,
+            title: !settings.fullscreen,
+            resize: settings.resizable,
+            scale: Scale::X1,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        };
+
+        let mut window = Window::new(
+            "CHIP 8",
+            width,
+            height,
+            window_options,
+        )
+        .unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+
+        window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+        if let (true, Some(screen)) = (settings.fullscreen, &screen) {
+            window.set_position(screen.x, screen.y);
+        }
+
+        Frontend::Window { window: Box::new(window), fullscreen: settings.fullscreen }
+    }
+
+    pub fn headless() -> Self {
+        Frontend::Headless { open: true }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        matches!(self, Frontend::Headless { .. })
+    }
+
+    pub fn is_open(&self) -> bool {
+        match self {
+            Frontend::Window { window, .. } => window.is_open(),
+            Frontend::Terminal(terminal) => terminal.is_open(),
+            Frontend::Headless { open } => *open,
+        }
+    }
+
+    // Shows pixels of colour numbers, in palette's colours.
+    pub fn present(&mut self, pixels: &[u8], palette: &Palette, width: usize, height: usize) {
+        match self {
+            Frontend::Window { window, .. } => {
+                let [_, r, g, b] = palette.background().to_be_bytes();
+                window.set_background_color(r as usize, g as usize, b as usize);
+                window.update_with_buffer(&palette.colours(pixels), width, height).unwrap();
+            },
+            Frontend::Terminal(terminal) => terminal.present(pixels, palette, width, height),
+            Frontend::Headless { .. } => (),
+        }
+        self.close_on_escape();
+    }
+
+    // Drops a fullscreen window on Esc, leaving nothing open.
+    fn close_on_escape(&mut self) {
+        if let Frontend::Window { window, fullscreen } = self {
+            if escaped(window, *fullscreen) {
+                *self = Frontend::Headless { open: false };
+            }
+        }
+    }
+
+    // Keeps showing pixels until the window is closed. Headless runs just stop.
+    pub fn halt(&mut self, pixels: &[u8], palette: &Palette, width: usize, height: usize) {
+        match self {
+            Frontend::Window { window, fullscreen } => {
+                let colours = palette.colours(pixels);
+                while window.is_open() && !escaped(window, *fullscreen) {
+                    window.update_with_buffer(&colours, width, height).unwrap();
+                }
+            },
+            Frontend::Terminal(terminal) => {
+                while terminal.is_open() {
+                    terminal.present(pixels, palette, width, height);
+                }
+            },
+            Frontend::Headless { open } => *open = false,
+        }
+        self.close_on_escape();
+    }
+
+    // The keypad keys held, as a mask with bit n for key n.
+    pub fn keypad(&self) -> u16 {
+        match self {
+            Frontend::Window { window, .. } => window
+                .get_keys()
+                .into_iter()
+                .filter_map(key_to_byte)
+                .fold(0, |keypad, key| keypad | 1 << key),
+            Frontend::Terminal(terminal) => terminal.keypad(),
+            Frontend::Headless { .. } => 0,
+        }
+    }
+
+    pub fn pressed(&mut self, hotkey: Hotkey) -> bool {
+        match self {
+            Frontend::Window { window, .. } => window.is_key_pressed(hotkey_to_key(hotkey), KeyRepeat::No),
+            Frontend::Terminal(terminal) => terminal.pressed(hotkey),
+            Frontend::Headless { .. } => false,
+        }
+    }
+
+    pub fn held(&self, hotkey: Hotkey) -> bool {
+        match self {
+            Frontend::Window { window, .. } => window.is_key_down(hotkey_to_key(hotkey)),
+            Frontend::Terminal(terminal) => terminal.held(hotkey),
+            Frontend::Headless { .. } => false,
+        }
+    }
+
+    // Lets the debugger have the terminal while the machine is stopped.
+    pub fn suspend(&mut self) {
+        if let Frontend::Terminal(terminal) = self {
+            terminal.suspend();
+        }
+    }
+}
+
+fn escaped(window: &Window, fullscreen: bool) -> bool {
+    fullscreen && window.is_key_down(Key::Escape)
+}
+
+fn hotkey_to_key(hotkey: Hotkey) -> Key {
+    match hotkey {
+        Hotkey::Break => Key::F12,
+        Hotkey::Save => Key::F5,
+        Hotkey::Load => Key::F9,
+        Hotkey::Screenshot => Key::F2,
+        Hotkey::Capture => Key::F3,
+        Hotkey::Rewind => Key::Backspace,
+    }
+}
+
+fn key_to_byte(key: Key) -> Option<Byte> {
+    match key {
+        Key::Key1 => Some(0x1),
+        Key::Key2 => Some(0x2),
+        Key::Key3 => Some(0x3),
+        Key::Key4 => Some(0xC),
+        Key::Q => Some(0x4),
+        Key::W => Some(0x5),
+        Key::E => Some(0x6),
+        Key::R => Some(0xD),
+        Key::A => Some(0x7),
+        Key::S => Some(0x8),
+        Key::D => Some(0x9),
+        Key::F => Some(0xE),
+        Key::Z => Some(0xA),
+        Key::X => Some(0x0),
+        Key::C => Some(0xB),
+        Key::V => Some(0xF),
+        _ => None,
+    }
+}
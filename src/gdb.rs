@@ -0,0 +1,496 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::chip::Chip;
+use crate::debugger::{Debugger, Goal, Stop};
+use crate::expr::Var;
+use crate::symbols::Symbols;
+use crate::types::*;
+
+// A GDB remote serial protocol stub.
+//
+// Registers are numbered V0 - VF (0 - 15, one byte each), I (16, two bytes),
+// PC (17, two bytes), SP (18, one byte, read-only), DT (19, one byte) and
+// ST (20, one byte). Multi-byte registers are sent little-endian.
+
+const REGISTER_COUNT: usize = 21;
+const STEPS_PER_POLL: usize = 1000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nn.chip8">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+pub fn serve(chip: Chip, symbols: Symbols, port: u16) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("127.0.0.1:{port}: {err}");
+            return;
+        },
+    };
+    println!("Waiting for GDB on 127.0.0.1:{port}");
+
+    let (stream, addr) = match listener.accept() {
+        Ok(connection) => connection,
+        Err(err) => {
+            println!("Waiting for GDB: {err}");
+            return;
+        },
+    };
+    println!("GDB connected from {addr}");
+
+    let mut conn = Connection::new(stream);
+    let mut debugger = Debugger::new(chip, symbols);
+
+    while let Some(packet) = conn.read_packet() {
+        match handle(&mut debugger, &mut conn, &packet) {
+            Some(reply) => conn.write_packet(&reply),
+            None => break,
+        }
+    }
+
+    println!("GDB disconnected");
+}
+
+// Returns the reply to a packet, or None if the session is over.
+fn handle(debugger: &mut Debugger, conn: &mut Connection, packet: &str) -> Option<String> {
+    let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+    let reply = match cmd {
+        "?" => "S05".to_string(),
+        "g" => read_registers(debugger.chip()),
+        "G" => ok_or_error(write_registers(debugger, args)),
+        "p" => read_register(debugger.chip(), args).unwrap_or_else(error),
+        "P" => ok_or_error(write_register(debugger, args)),
+        "m" => read_memory(debugger.chip(), args).unwrap_or_else(error),
+        "M" => ok_or_error(write_memory(debugger, args)),
+        "s" => match resume_at(debugger, args) {
+            Ok(()) => stop_reply(debugger.step(1)),
+            Err(err) => error(err),
+        },
+        "c" => match resume_at(debugger, args) {
+            Ok(()) => go(debugger, conn),
+            Err(err) => error(err),
+        },
+        "Z" => ok_or_error(breakpoint(args).map(|addr| debugger.set_breakpoint(addr, None))),
+        // Removing a breakpoint that is not there leaves things as asked.
+        "z" => ok_or_error(breakpoint(args).map(|addr| { let _ = debugger.delete_breakpoint(addr); })),
+        "H" => "OK".to_string(),
+        "D" => { conn.write_packet("OK"); return None; }
+        "k" => return None,
+        "q" => query(args),
+        _ => String::new(),
+    };
+
+    Some(reply)
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        "PacketSize=4000;qXfer:features:read+".to_string()
+    } else if args == "Attached" {
+        "1".to_string()
+    } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        xfer(TARGET_XML, range).unwrap_or_else(error)
+    } else {
+        String::new()
+    }
+}
+
+// Serves the part of a document requested as offset,length.
+fn xfer(doc: &str, range: &str) -> Result<String, String> {
+    let (offset, len) = parse_pair(range)?;
+    let start = usize::min(offset, doc.len());
+    let end = usize::min(start + len, doc.len());
+    let marker = if end == doc.len() { 'l' } else { 'm' };
+
+    Ok(format!("{marker}{}", &doc[start .. end]))
+}
+
+fn ok_or_error(result: Result<(), String>) -> String {
+    match result {
+        Ok(()) => "OK".to_string(),
+        Err(err) => error(err),
+    }
+}
+
+fn error(err: String) -> String {
+    println!("GDB request failed: {err}");
+    "E01".to_string()
+}
+
+fn register_value(chip: &Chip, n: usize) -> (u32, usize) {
+    match n {
+        0 ..= 15 => (chip.v(n) as u32, 1),
+        16 => (chip.i() as u32, 2),
+        17 => (chip.pc() as u32, 2),
+        18 => (chip.stack_depth() as u32, 1),
+        19 => (chip.delay_timer() as u32, 1),
+        _ => (chip.sound_timer() as u32, 1),
+    }
+}
+
+fn set_register(debugger: &mut Debugger, n: usize, value: u32) -> Result<(), String> {
+    let var = match n {
+        0 ..= 15 => Var::V(n),
+        16 => Var::I,
+        17 => Var::Pc,
+        18 => return Err("SP is read-only".to_string()),
+        19 => Var::Dt,
+        20 => Var::St,
+        _ => return Err(format!("no register {n}")),
+    };
+    debugger.set(var, value)
+}
+
+fn encode_register(value: u32, size: usize) -> String {
+    let bytes = value.to_le_bytes();
+    to_hex(&bytes[.. size])
+}
+
+fn read_registers(chip: &Chip) -> String {
+    (0 .. REGISTER_COUNT)
+        .map(|n| {
+            let (value, size) = register_value(chip, n);
+            encode_register(value, size)
+        })
+        .collect()
+}
+
+fn write_registers(debugger: &mut Debugger, args: &str) -> Result<(), String> {
+    let mut bytes = from_hex(args)?.into_iter();
+
+    for n in 0 .. REGISTER_COUNT {
+        let (old, size) = register_value(debugger.chip(), n);
+        let mut le = [0; 4];
+        for b in le.iter_mut().take(size) {
+            *b = bytes.next().ok_or("register packet too short")?;
+        }
+        let value = u32::from_le_bytes(le);
+        if value != old && n != 18 {
+            set_register(debugger, n, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_register(chip: &Chip, args: &str) -> Result<String, String> {
+    let n = parse_hex(args)? as usize;
+
+    if n >= REGISTER_COUNT {
+        return Err(format!("no register {n}"));
+    }
+
+    let (value, size) = register_value(chip, n);
+    Ok(encode_register(value, size))
+}
+
+fn write_register(debugger: &mut Debugger, args: &str) -> Result<(), String> {
+    let (n, value) = args.split_once('=').ok_or("bad register write")?;
+    let mut le = [0; 4];
+
+    for (b, v) in le.iter_mut().zip(from_hex(value)?) {
+        *b = v;
+    }
+
+    set_register(debugger, parse_hex(n)? as usize, u32::from_le_bytes(le))
+}
+
+fn memory_range(chip: &Chip, range: &str) -> Result<(usize, usize), String> {
+    let (addr, len) = parse_pair(range)?;
+
+    if addr + len > chip.memory().len() {
+        return Err(format!("range out of memory: {addr:04x} + {len}"));
+    }
+
+    Ok((addr, len))
+}
+
+fn read_memory(chip: &Chip, args: &str) -> Result<String, String> {
+    let (addr, len) = memory_range(chip, args)?;
+    Ok(to_hex(&chip.memory()[addr .. addr + len]))
+}
+
+fn write_memory(debugger: &mut Debugger, args: &str) -> Result<(), String> {
+    let (range, data) = args.split_once(':').ok_or("bad memory write")?;
+    let (addr, len) = memory_range(debugger.chip(), range)?;
+    let bytes = from_hex(data)?;
+
+    if bytes.len() != len {
+        return Err("memory write length mismatch".to_string());
+    }
+
+    debugger.write_memory(addr, &bytes)
+}
+
+fn breakpoint(args: &str) -> Result<Address, String> {
+    match args.split(',').collect::<Vec<&str>>()[..] {
+        ["0", addr, _] => Ok(parse_hex(addr)? as Address),
+        _ => Err(format!("unsupported breakpoint: {args}")),
+    }
+}
+
+fn resume_at(debugger: &mut Debugger, args: &str) -> Result<(), String> {
+    if !args.is_empty() {
+        debugger.set(Var::Pc, parse_hex(args)?)?;
+    }
+    Ok(())
+}
+
+// Runs until a breakpoint, an interrupt from GDB or the window closing.
+fn go(debugger: &mut Debugger, conn: &mut Connection) -> String {
+    let mut goal = Goal::Never;
+
+    loop {
+        if let Some(stop) = debugger.run_for(&mut goal, STEPS_PER_POLL) {
+            return stop_reply(stop);
+        }
+        if conn.poll_interrupt() {
+            return "S02".to_string();
+        }
+    }
+}
+
+// Stops are reported as signals: SIGINT for an interrupt, SIGILL for an
+// illegal instruction and SIGTRAP for the rest, or an exit once the window
+// is closed.
+fn stop_reply(stop: Stop) -> String {
+    match stop {
+        Stop::Interrupted(_) => "S02".to_string(),
+        Stop::Illegal(..) => "S04".to_string(),
+        Stop::Closed => "W00".to_string(),
+        Stop::Done | Stop::Breakpoint(..) | Stop::ConditionError(..) => "S05".to_string(),
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 16).map_err(|_| format!("bad hex number: {s}"))
+}
+
+fn parse_pair(s: &str) -> Result<(usize, usize), String> {
+    let (a, b) = s.split_once(',').ok_or_else(|| format!("bad range: {s}"))?;
+    Ok((parse_hex(a)? as usize, parse_hex(b)? as usize))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(format!("bad hex data: {s}"));
+    }
+
+    (0 .. s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i .. i + 2], 16).map_err(|_| format!("bad hex data: {s}")))
+        .collect()
+}
+
+struct Connection {
+    stream: TcpStream,
+    buf: VecDeque<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Self { stream, buf: VecDeque::new() }
+    }
+
+    fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; 1024];
+        let n = self.stream.read(&mut chunk)?;
+        self.buf.extend(&chunk[.. n]);
+        Ok(n)
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        if self.buf.is_empty() {
+            match self.fill() {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => (),
+            }
+        }
+        self.buf.pop_front()
+    }
+
+    // Reads the next packet, acknowledging it. Acks from GDB and interrupts
+    // received while stopped are skipped.
+    fn read_packet(&mut self) -> Option<String> {
+        loop {
+            while self.read_byte()? != b'$' {}
+
+            let mut data = Vec::new();
+            let mut sum: u8 = 0;
+
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    b => {
+                        sum = sum.wrapping_add(b);
+                        data.push(b);
+                    },
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let checksum = std::str::from_utf8(&checksum).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+
+            if checksum == Some(sum) {
+                self.write_raw(b"+");
+                return Some(unescape(&data));
+            }
+
+            self.write_raw(b"-");
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${data}#{sum:02x}");
+        self.write_raw(packet.as_bytes());
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) {
+        if let Err(err) = self.stream.write_all(bytes) {
+            println!("GDB connection: {err}");
+        }
+    }
+
+    // Checks, without blocking, whether GDB has sent an interrupt (0x03).
+    fn poll_interrupt(&mut self) -> bool {
+        if self.stream.set_nonblocking(true).is_ok() {
+            while let Ok(n) = self.fill() {
+                if n == 0 {
+                    break;
+                }
+            }
+            let _ = self.stream.set_nonblocking(false);
+        }
+
+        match self.buf.iter().position(|b| *b == 0x03) {
+            Some(i) => {
+                self.buf.remove(i);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+fn unescape(data: &[u8]) -> String {
+    let mut out = Vec::new();
+    let mut bytes = data.iter();
+
+    while let Some(b) = bytes.next() {
+        match b {
+            b'}' => out.extend(bytes.next().map(|b| b ^ 0x20)),
+            b => out.push(*b),
+        }
+    }
+
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::Frontend;
+
+    // LD I, CLS, DRW and ADD, then a delay loop from 0x208 jumping back to 0x202.
+    const ROM: [u8; 20] = [
+        0xA0, 0x00, 0x00, 0xE0, 0xD0, 0x15, 0x70, 0x01, 0x62, 0x05,
+        0xF2, 0x15, 0xF2, 0x07, 0x32, 0x00, 0x12, 0x0C, 0x12, 0x02,
+    ];
+
+    // Runs packets through handle, returning the replies.
+    fn script(packets: &[&str]) -> Vec<Option<String>> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut conn = Connection::new(stream);
+
+        let mut chip = Chip::new(Frontend::headless());
+        chip.load_font();
+        chip.load_rom(ROM.to_vec());
+        let mut debugger = Debugger::new(chip, Symbols::default());
+
+        let replies = packets.iter().map(|packet| handle(&mut debugger, &mut conn, packet)).collect();
+        drop(client);
+        replies
+    }
+
+    fn reply(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn registers() {
+        let replies = script(&["?", "P3=2a", "p3", "P10=0103", "p10", "p11", "P12=01", "p15", "g"]);
+        let g = format!("000000{}{}{}{}000000", "2a", "00".repeat(12), "0103", "0002");
+
+        assert_eq!(replies, [
+            reply("S05"), reply("OK"), reply("2a"), reply("OK"), reply("0103"), reply("0002"), reply("E01"), reply("E01"), Some(g),
+        ]);
+    }
+
+    #[test]
+    fn memory() {
+        let replies = script(&["m200,4", "M300,2:beef", "m300,2", "M300,2:be", "mfff,2", "m200"]);
+
+        assert_eq!(replies, [
+            reply("a00000e0"), reply("OK"), reply("beef"), reply("E01"), reply("E01"), reply("E01"),
+        ]);
+    }
+
+    #[test]
+    fn step_and_continue() {
+        let replies = script(&["s", "p11", "Z0,20c,2", "c", "p11", "z0,20c,2", "s206", "p11", "Z1,20c,2"]);
+
+        assert_eq!(replies, [
+            reply("S05"), reply("0202"), reply("OK"), reply("S05"), reply("0c02"), reply("OK"), reply("S05"), reply("0802"), reply("E01"),
+        ]);
+    }
+
+    #[test]
+    fn queries_and_unknown_packets() {
+        let replies = script(&["qSupported:multiprocess+", "qAttached", "qXfer:features:read:target.xml:0,5", "vMustReplyEmpty", "\u{e9}t", ""]);
+
+        assert_eq!(replies, [
+            reply("PacketSize=4000;qXfer:features:read+"), reply("1"), reply("m<?xml"), reply(""), reply(""), reply(""),
+        ]);
+    }
+
+    #[test]
+    fn end_of_session() {
+        assert_eq!(script(&["k"]), [None]);
+        assert_eq!(script(&["D"]), [None]);
+    }
+}
@@ -1,12 +1,70 @@
-pub fn get_line() -> Option<String> {
-    let mut line = String::new();
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Lines};
 
-    match std::io::stdin().read_line(&mut line) {
-        Ok(0) => None,
-        Ok(_) => Some(line.trim_end().to_string()),
-        Err(err) => {
-            println!("{}", err);
-            None
-        },
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+pub enum LineReader {
+    Interactive(Box<DefaultEditor>),
+    Script(Lines<BufReader<File>>),
+}
+
+impl LineReader {
+    pub fn interactive() -> Self {
+        Self::Interactive(Box::new(DefaultEditor::new().unwrap()))
+    }
+
+    pub fn script(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::Script(BufReader::new(file).lines()))
+    }
+
+    pub fn get_line(&mut self, prompt: &str) -> Option<String> {
+        match self {
+            Self::Interactive(editor) => read_interactive(editor, prompt),
+            Self::Script(lines) => read_script(lines, prompt),
+        }
     }
 }
+
+fn read_interactive(editor: &mut DefaultEditor, prompt: &str) -> Option<String> {
+    loop {
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    let _ = editor.add_history_entry(line.as_str());
+                }
+                return Some(line.trim_end().to_string());
+            },
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return None,
+            Err(err) => {
+                println!("{}", err);
+                return None;
+            },
+        }
+    }
+}
+
+// Script lines are echoed after the prompt, as if typed. Blank lines and
+// lines starting with # are skipped rather than repeating the last command.
+fn read_script(lines: &mut Lines<BufReader<File>>, prompt: &str) -> Option<String> {
+    for line in lines.by_ref() {
+        match line {
+            Ok(line) => {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                println!("{prompt}{line}");
+                return Some(line.to_string());
+            },
+            Err(err) => {
+                println!("{}", err);
+                return None;
+            },
+        }
+    }
+
+    None
+}
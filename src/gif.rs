@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::image::Image;
+
+// Animated GIF writer. Each frame carries its own colour table, built from
+// the colours it uses, so the palette need not be known up front. The file
+// is only complete once finish is called.
+
+const MAX_CODE: u16 = 4095;
+
+pub struct Gif {
+    out: BufWriter<File>,
+}
+
+impl Gif {
+    pub fn create(path: &str, width: usize, height: usize) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&(width as u16).to_le_bytes())?;
+        out.write_all(&(height as u16).to_le_bytes())?;
+        out.write_all(&[0, 0, 0])?;
+
+        // Loop forever.
+        out.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        Ok(Self { out })
+    }
+
+    // Adds a frame shown for delay hundredths of a second.
+    pub fn frame(&mut self, image: &Image, delay: u16) -> io::Result<()> {
+        let mut colours: Vec<u32> = Vec::new();
+        let mut indices = Vec::with_capacity(image.pixels.len());
+
+        for pixel in image.pixels.iter() {
+            let index = match colours.iter().position(|colour| colour == pixel) {
+                Some(index) => index,
+                None => {
+                    colours.push(*pixel);
+                    colours.len() - 1
+                },
+            };
+            if index > 255 {
+                return Err(io::Error::other("more than 256 colours in a frame"));
+            }
+            indices.push(index as u8);
+        }
+
+        // The colour table has 2^bits entries.
+        let bits = (1 ..= 8).find(|bits| colours.len() <= 1 << bits).unwrap();
+        colours.resize(1 << bits, 0);
+
+        let delay = delay.to_le_bytes();
+        self.out.write_all(&[0x21, 0xF9, 4, 0x04, delay[0], delay[1], 0, 0])?;
+
+        self.out.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.out.write_all(&(image.width as u16).to_le_bytes())?;
+        self.out.write_all(&(image.height as u16).to_le_bytes())?;
+        self.out.write_all(&[0x80 | (bits - 1)])?;
+        for colour in colours {
+            self.out.write_all(&colour.to_be_bytes()[1 ..])?;
+        }
+
+        let min_size = bits.max(2);
+        self.out.write_all(&[min_size])?;
+        for block in lzw(&indices, min_size).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0])
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.out.write_all(&[0x3B])?;
+        self.out.flush()
+    }
+}
+
+// Variable width LZW as GIF uses it: codes start one bit wider than the
+// literals, grow as the table fills and are packed least significant bit
+// first. A full table is cleared and started again.
+fn lzw(indices: &[u8], min_size: u8) -> Vec<u8> {
+    let mut lzw = Lzw {
+        table: HashMap::new(),
+        min_size,
+        width: min_size + 1,
+        hi: (1 << min_size) + 1,
+        out: Vec::new(),
+        acc: 0,
+        count: 0,
+    };
+
+    lzw.write(lzw.clear());
+
+    let mut code: Option<u16> = None;
+    for index in indices.iter() {
+        let prefix = match code {
+            Some(prefix) => prefix,
+            None => {
+                code = Some(*index as u16);
+                continue;
+            },
+        };
+
+        if let Some(found) = lzw.table.get(&(prefix, *index)) {
+            code = Some(*found);
+            continue;
+        }
+
+        lzw.write(prefix);
+        code = Some(*index as u16);
+
+        if lzw.next_code() {
+            lzw.table.insert((prefix, *index), lzw.hi);
+        }
+    }
+
+    if let Some(code) = code {
+        lzw.write(code);
+        lzw.next_code();
+    }
+    lzw.write(lzw.clear() + 1);
+
+    if lzw.count > 0 {
+        lzw.out.push(lzw.acc as u8);
+    }
+    lzw.out
+}
+
+struct Lzw {
+    table: HashMap<(u16, u8), u16>,
+    min_size: u8,
+    width: u8,
+    hi: u16,
+    out: Vec<u8>,
+    acc: u32,
+    count: u8,
+}
+
+impl Lzw {
+    fn clear(&self) -> u16 {
+        1 << self.min_size
+    }
+
+    fn write(&mut self, code: u16) {
+        self.acc |= (code as u32) << self.count;
+        self.count += self.width;
+        while self.count >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Takes the next code, returning false if the table was full and has
+    // been cleared instead.
+    fn next_code(&mut self) -> bool {
+        self.hi += 1;
+        if self.hi == 1 << self.width {
+            self.width += 1;
+        }
+        if self.hi == MAX_CODE {
+            self.write(self.clear());
+            self.table.clear();
+            self.hi = self.clear() + 1;
+            self.width = self.min_size + 1;
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decodes GIF LZW, returning the indices, the number of clear codes and
+    // the widest code.
+    fn unlzw(bytes: &[u8], min_size: u8) -> (Vec<u8>, usize, u8) {
+        let clear = 1 << min_size;
+        let mut width = min_size + 1;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut last: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        let mut clears = 0;
+        let mut bit = 0;
+        let mut widest = 0;
+
+        loop {
+            widest = widest.max(width);
+            let mut code = 0;
+            for n in 0 .. width as usize {
+                code |= ((bytes[(bit + n) / 8] >> ((bit + n) % 8)) as usize & 1) << n;
+            }
+            bit += width as usize;
+
+            if code == clear {
+                table = (0 ..= clear + 1).map(|n| vec![n as u8]).collect();
+                width = min_size + 1;
+                last = None;
+                clears += 1;
+                continue;
+            }
+            if code == clear + 1 {
+                break;
+            }
+
+            let entry = match &last {
+                Some(last) if code == table.len() => [last.clone(), vec![last[0]]].concat(),
+                _ => table[code].clone(),
+            };
+            if let Some(last) = last {
+                table.push([last, vec![entry[0]]].concat());
+            }
+            out.extend_from_slice(&entry);
+            last = Some(entry);
+
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+
+        assert_eq!(bytes.len(), bit.div_ceil(8));
+        (out, clears, widest)
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 1u32;
+        (0 .. len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    fn known_bytes() {
+        let rows = [
+            "1111122222", "1111122222", "1111122222", "1110000222", "1110000222",
+            "2220000111", "2220000111", "2222211111", "2222211111", "2222211111",
+        ];
+        let indices: Vec<u8> = rows.concat().bytes().map(|b| b - b'0').collect();
+
+        assert_eq!(lzw(&indices, 2), [
+            0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75,
+            0xEC, 0x95, 0xFA, 0xA8, 0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn round_trips() {
+        for indices in [vec![], vec![3], vec![1, 1, 1, 1, 1, 1, 1]] {
+            assert_eq!(unlzw(&lzw(&indices, 2), 2).0, indices);
+        }
+    }
+
+    #[test]
+    fn widths_grow_to_twelve() {
+        let indices: Vec<u8> = noise(12000).iter().map(|n| n & 3).collect();
+        let (decoded, clears, widest) = unlzw(&lzw(&indices, 2), 2);
+        assert_eq!(decoded, indices);
+        assert_eq!((clears, widest), (1, 12));
+    }
+
+    #[test]
+    fn full_table_clears() {
+        let indices = noise(50000);
+        let (decoded, clears, _) = unlzw(&lzw(&indices, 8), 8);
+        assert_eq!(decoded, indices);
+        assert!(clears > 2, "{clears} clears");
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::base64;
+use crate::image::Image;
+
+// Inline images for terminals that can show them, as Sixel or with the
+// kitty graphics protocol.
+//
+// Support is found by asking the terminal: Sixel terminals list attribute 4
+// in their reply to the primary device attributes request (ESC [ c), and
+// kitty answers a query for a one pixel image before it. Every terminal
+// answers the device attributes request, so its reply marks the end of the
+// answers.
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Graphics {
+    Sixel,
+    Kitty,
+}
+
+impl Graphics {
+    // Asks whether the terminal can show these graphics. The terminal must
+    // be in raw mode, so that the answers can be read as they come.
+    pub fn supported(self, out: &mut impl Write) -> io::Result<bool> {
+        match self {
+            Graphics::Sixel => write!(out, "\x1b[c")?,
+            Graphics::Kitty => write!(out, "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c")?,
+        }
+        out.flush()?;
+
+        let reply = read_reply();
+        let attributes = match device_attributes(&reply) {
+            Some(attributes) => attributes,
+            None => return Ok(false),
+        };
+
+        Ok(match self {
+            Graphics::Sixel => attributes.iter().any(|attribute| attribute == "4"),
+            Graphics::Kitty => reply.windows(10).any(|w| w == b"\x1b_Gi=31;OK"),
+        })
+    }
+
+    pub fn encode(self, image: &Image) -> String {
+        match self {
+            Graphics::Sixel => sixel(image),
+            Graphics::Kitty => kitty(image),
+        }
+    }
+
+    // Removes any image shown, before the terminal is given back.
+    pub fn clear(self) -> &'static str {
+        match self {
+            Graphics::Sixel => "",
+            Graphics::Kitty => "\x1b_Ga=d,q=2\x1b\\",
+        }
+    }
+}
+
+// Sixel draws six rows at a time, a pass for each colour in the band.
+// Each character is 63 plus a bit for each of the six pixels it covers,
+// top first, and "!n" repeats the next character n times.
+fn sixel(image: &Image) -> String {
+    let mut colours: Vec<u32> = Vec::new();
+    for pixel in image.pixels.iter() {
+        if !colours.contains(pixel) {
+            colours.push(*pixel);
+        }
+    }
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", image.width, image.height);
+
+    for (n, colour) in colours.iter().enumerate() {
+        let [_, r, g, b] = colour.to_be_bytes();
+        let percent = |c: u8| (c as u32 * 100 + 127) / 255;
+        out.push_str(&format!("#{n};2;{};{};{}", percent(r), percent(g), percent(b)));
+    }
+
+    for top in (0 .. image.height).step_by(6) {
+        for (n, colour) in colours.iter().enumerate() {
+            let sixels: Vec<u8> = (0 .. image.width)
+                .map(|x| {
+                    (0 .. 6)
+                        .filter(|dy| top + dy < image.height && image.pixels[(top + dy) * image.width + x] == *colour)
+                        .fold(0, |bits, dy| bits | 1 << dy)
+                })
+                .collect();
+
+            if sixels.iter().all(|bits| *bits == 0) {
+                continue;
+            }
+
+            out.push_str(&format!("#{n}"));
+            for run in sixels.chunk_by(|a, b| a == b) {
+                let c = (63 + run[0]) as char;
+                if run.len() > 3 {
+                    out.push_str(&format!("!{}{c}", run.len()));
+                } else {
+                    out.extend(std::iter::repeat_n(c, run.len()));
+                }
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+// The kitty protocol takes the RGB pixels as base64, in chunks with m=1 on
+// all but the last. Sending the same image and placement ids replaces the
+// last frame, q=2 stops the terminal replying and C=1 leaves the cursor.
+fn kitty(image: &Image) -> String {
+    let rgb: Vec<u8> = image.pixels.iter().flat_map(|pixel| pixel.to_be_bytes()[1 ..].to_vec()).collect();
+    let data = base64::encode(&rgb);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+
+    for (n, chunk) in chunks.iter().enumerate() {
+        let more = (n + 1 < chunks.len()) as u8;
+        if n == 0 {
+            out.push_str(&format!("\x1b_Ga=T,f=24,s={},v={},i=1,p=1,q=2,C=1,m={more};", image.width, image.height));
+        } else {
+            out.push_str(&format!("\x1b_Gm={more};"));
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+
+    out
+}
+
+// The parameters of a primary device attributes reply, ESC [ ? 62 ; 4 c.
+fn device_attributes(reply: &[u8]) -> Option<Vec<String>> {
+    let start = reply.windows(3).position(|w| w == b"\x1b[?")? + 3;
+    let end = start + reply[start ..].iter().position(|c| *c == b'c')?;
+    let params = std::str::from_utf8(&reply[start .. end]).ok()?;
+
+    Some(params.split(';').map(|param| param.to_string()).collect())
+}
+
+// Reads what the terminal sends until the device attributes reply or a
+// timeout.
+#[cfg(unix)]
+fn read_reply() -> Vec<u8> {
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+
+    while device_attributes(&reply).is_none() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+
+        let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut fd, 1, left.as_millis() as libc::c_int) } <= 0 {
+            break;
+        }
+
+        let mut buffer = [0u8; 256];
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if n <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buffer[.. n as usize]);
+    }
+
+    reply
+}
+
+// Without a way to wait for the reply, assume no support.
+#[cfg(not(unix))]
+fn read_reply() -> Vec<u8> {
+    Vec::new()
+}
@@ -1,11 +1,16 @@
 const COLUMNS: usize = 8;
 
-pub fn dump(bytes: &Vec<u8>) {
+pub fn dump(bytes: &[u8], base: usize) {
+    dump_annotated(bytes, base, &[]);
+}
+
+// Notes are printed at the end of the row containing their address.
+pub fn dump_annotated(bytes: &[u8], base: usize, notes: &[(usize, String)]) {
     let rows = get_rows(bytes);
-    let lines = get_lines(rows);
+    let lines = get_lines(rows, base, notes);
     let rle = get_rle(lines);
 
-    let mut addr = 0;
+    let mut addr = base;
 
     for (len, line) in rle.iter() {
         if *len > 1 {
@@ -20,24 +25,53 @@ pub fn dump(bytes: &Vec<u8>) {
     }
 }
 
-fn get_rows(bytes: &Vec<u8>) -> Vec<Vec<u8>> {
+fn get_rows(bytes: &[u8]) -> Vec<Vec<u8>> {
     let mut rows = Vec::new();
     let mut i = 0;
 
     while i < bytes.len() {
         let j = usize::min(i + COLUMNS, bytes.len());
-        rows.push(bytes[i..j].iter().cloned().collect());
+        rows.push(bytes[i..j].to_vec());
         i += COLUMNS;
     }
 
     rows
 }
 
-fn get_lines(rows: Vec<Vec<u8>>) -> Vec<String> {
-    rows.iter().map(concat).collect()
+fn get_lines(rows: Vec<Vec<u8>>, base: usize, notes: &[(usize, String)]) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for (i, row) in rows.iter().enumerate() {
+        let addr = base + i * COLUMNS;
+        let mut line = format!("{:width$}  |{}|", concat(row), ascii(row), width = 3 * COLUMNS - 1);
+
+        let row_notes: Vec<&str> = notes
+            .iter()
+            .filter(|(note_addr, _)| (addr .. addr + COLUMNS).contains(note_addr))
+            .map(|(_, note)| note.as_str())
+            .collect();
+
+        if !row_notes.is_empty() {
+            line.push_str("  ");
+            line.push_str(&row_notes.join(", "));
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn ascii(row: &[u8]) -> String {
+    row.iter()
+        .map(|byte| match byte {
+            0x20 ..= 0x7E => *byte as char,
+            _ => '.',
+        })
+        .collect()
 }
 
-fn concat(row: &Vec<u8>) -> String {
+fn concat(row: &[u8]) -> String {
     let mut line = String::new();
 
     for (i, byte) in row.iter().enumerate() {
@@ -61,7 +95,7 @@ fn get_rle(lines: Vec<String>) -> Vec<(usize, String)> {
     rle
 }
 
-fn get_run(lines: &Vec<String>, p: &mut usize) -> (usize, String) {
+fn get_run(lines: &[String], p: &mut usize) -> (usize, String) {
     let mut run = (0, String::new());
 
     for i in *p .. lines.len() {
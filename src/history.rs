@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+
+use crate::types::*;
+
+pub const DEFAULT_HISTORY_SIZE: usize = 100_000;
+
+// What is needed to take back one step: the registers and stack before it,
+// the old contents of any memory and pixels it changed, and the resolution,
+// keypad, random number generator and movie frame before it.
+pub struct Undo {
+    pub pc: Address,
+    pub i: Address,
+    pub v: [Byte; 16],
+    pub stack: Vec<Address>,
+    pub delay: u8,
+    pub sound: u8,
+    pub memory: (usize, Vec<Byte>),
+    pub hires: bool,
+    pub display: Vec<(usize, u8)>,
+    pub keypad: u16,
+    pub rng: u64,
+    pub movie: Option<usize>,
+}
+
+// The most recent steps, oldest first, forgetting the oldest beyond size.
+pub struct History {
+    steps: VecDeque<Undo>,
+    size: usize,
+}
+
+impl History {
+    pub fn new(size: usize) -> Self {
+        Self { steps: VecDeque::new(), size }
+    }
+
+    pub fn push(&mut self, undo: Undo) {
+        if self.size == 0 {
+            return;
+        }
+        if self.steps.len() == self.size {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(undo);
+    }
+
+    pub fn pop(&mut self) -> Option<Undo> {
+        self.steps.pop_back()
+    }
+
+    pub fn last(&self) -> Option<&Undo> {
+        self.steps.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn resize(&mut self, size: usize) {
+        self.size = size;
+        while self.steps.len() > size {
+            self.steps.pop_front();
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::Path;
+
+// Writes images of the display. Pixels are 0RGB, as in the window's buffer.
+//
+// PNG is written uncompressed (stored deflate blocks), which keeps it simple
+// and is small enough at these sizes. PBM and PGM are the binary (P4 and P5)
+// variants; PBM pixels are black where the colour is darker than mid grey.
+
+#[derive(PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    pub fn new(pixels: &[u32], width: usize, height: usize) -> Self {
+        Self { width, height, pixels: pixels.to_vec() }
+    }
+
+    pub fn scaled(&self, scale: usize) -> Self {
+        let width = self.width * scale;
+        let height = self.height * scale;
+        let pixels = (0 .. width * height)
+            .map(|n| self.pixels[(n / width / scale) * self.width + (n % width) / scale])
+            .collect();
+
+        Self { width, height, pixels }
+    }
+
+    // Stretches to width x height, taking the nearest pixel.
+    pub fn resized(&self, width: usize, height: usize) -> Self {
+        let pixels = (0 .. width * height)
+            .map(|n| self.pixels[(n / width) * self.height / height * self.width + (n % width) * self.width / width])
+            .collect();
+
+        Self { width, height, pixels }
+    }
+
+    // Picks the format from the extension of path.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+
+        let bytes = match extension.to_lowercase().as_str() {
+            "png" => self.png(),
+            "pbm" => self.pbm(),
+            "pgm" => self.pgm(),
+            _ => return Err(format!("{path}: unknown image format, use .png, .pbm or .pgm")),
+        };
+
+        fs::write(path, bytes).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((3 * self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+            for pixel in row {
+                raw.extend_from_slice(&pixel.to_be_bytes()[1 ..]);
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut out, b"IHDR", &header);
+        chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    pub fn pbm(&self) -> Vec<u8> {
+        let mut out = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+
+        for row in self.pixels.chunks(self.width) {
+            for pixels in row.chunks(8) {
+                out.push(pixels.iter().enumerate().fold(0, |byte, (n, pixel)| {
+                    byte | ((luma(*pixel) < 128) as u8) << (7 - n)
+                }));
+            }
+        }
+
+        out
+    }
+
+    pub fn pgm(&self) -> Vec<u8> {
+        let mut out = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend(self.pixels.iter().map(|pixel| luma(*pixel)));
+        out
+    }
+}
+
+fn luma(pixel: u32) -> u8 {
+    let [_, r, g, b] = pixel.to_be_bytes();
+    ((299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000) as u8
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start ..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+
+    for (n, block) in blocks.iter().enumerate() {
+        let last = n + 1 == blocks.len();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0 .. 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resizing() {
+        let image = Image::new(&[1, 2, 3, 4], 2, 2);
+        assert_eq!(image.resized(4, 4).pixels, image.scaled(2).pixels);
+        assert_eq!(image.resized(4, 4).resized(2, 2).pixels, image.pixels);
+        assert_eq!(image.resized(1, 1).pixels, [1]);
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn stored_blocks_split() {
+        let data: Vec<u8> = (0 .. 0xFFFF + 10).map(|n| n as u8).collect();
+        let out = zlib_stored(&data);
+
+        assert_eq!(out[.. 2], [0x78, 0x01]);
+        assert_eq!(out[2 .. 7], [0, 0xFF, 0xFF, 0, 0]);
+        assert_eq!(out[7 .. 7 + 0xFFFF], data[.. 0xFFFF]);
+
+        let second = 7 + 0xFFFF;
+        assert_eq!(out[second .. second + 5], [1, 10, 0, 0xF5, 0xFF]);
+        assert_eq!(out[second + 5 .. second + 15], data[0xFFFF ..]);
+        assert_eq!(out[second + 15 ..], adler32(&data).to_be_bytes());
+    }
+
+    #[test]
+    fn stored_empty() {
+        let out = zlib_stored(&[]);
+        assert_eq!(out, [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1]);
+    }
+}
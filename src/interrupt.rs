@@ -0,0 +1,17 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// SIGINT (Ctrl-C) asks a running chip to break into the debugger rather than
+// ending the program.
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install() {
+    if let Err(err) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+        println!("Cannot catch Ctrl-C: {err}");
+    }
+}
+
+// Returns whether SIGINT arrived since the last call.
+pub fn take() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+
+// Just enough JSON for the debug adapter protocol.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> &Value {
+        match self {
+            Value::Object(pairs) => pairs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v)
+                .unwrap_or(&Value::Null),
+            _ => &Value::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Value] {
+        match self {
+            Value::Array(values) => values,
+            _ => &[],
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Self {
+        Value::Array(values)
+    }
+}
+
+pub fn object(pairs: Vec<(&str, Value)>) -> Value {
+    Value::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                write!(f, "]")
+            },
+            Value::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{v}")?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub fn parse(s: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: s.chars().collect(), pos: 0 };
+    let value = parser.value()?;
+
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err("trailing characters after JSON value".to_string());
+    }
+
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.chars.get(self.pos).copied().ok_or("unexpected end of JSON")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        for c in word.chars() {
+            if self.next()? != c {
+                return Err(format!("expected {word}"));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.chars.get(self.pos) {
+            Some('n') => self.expect("null").map(|_| Value::Null),
+            Some('t') => self.expect("true").map(|_| Value::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(_) => self.number(),
+            None => Err("unexpected end of JSON".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| "+-.eE0123456789".contains(*c)) {
+            self.pos += 1;
+        }
+
+        let s: String = self.chars[start .. self.pos].iter().collect();
+        s.parse().map(Value::Number).map_err(|_| format!("bad JSON number: {s}"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        self.expect("\"")?;
+
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => s.push(self.unicode_escape()?),
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    // The character after \u. Characters outside the basic plane come as a
+    // pair of UTF-16 surrogates, \ud83d\ude00; a lone surrogate becomes U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xD800 .. 0xDC00).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or('\u{fffd}'));
+        }
+
+        if self.chars.get(self.pos .. self.pos + 2) != Some(&['\\', 'u']) {
+            return Ok('\u{fffd}');
+        }
+        let start = self.pos;
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xDC00 .. 0xE000).contains(&low) {
+            self.pos = start;
+            return Ok('\u{fffd}');
+        }
+
+        Ok(char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).unwrap())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let hex: String = (0 .. 4).map(|_| self.next()).collect::<Result<_, _>>()?;
+        u32::from_str_radix(&hex, 16).map_err(|_| format!("bad JSON escape: \\u{hex}"))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut values = Vec::new();
+        self.expect("[")?;
+        self.skip_whitespace();
+
+        if self.chars.get(self.pos) == Some(&']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => (),
+                ']' => return Ok(Value::Array(values)),
+                c => return Err(format!("unexpected {c} in JSON array")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        let mut pairs = Vec::new();
+        self.expect("{")?;
+        self.skip_whitespace();
+
+        if self.chars.get(self.pos) == Some(&'}') {
+            self.pos += 1;
+            return Ok(Value::Object(pairs));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            pairs.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => (),
+                '}' => return Ok(Value::Object(pairs)),
+                c => return Err(format!("unexpected {c} in JSON object")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        assert_eq!(parse(r#""a\"b\\c\/d\n\r\t\b\f""#), Ok(Value::from("a\"b\\c/d\n\r\t\u{8}\u{c}")));
+        assert_eq!(parse(r#""\u0041\u00e9\u20AC""#), Ok(Value::from("Aé€")));
+
+        let s = "quote \" backslash \\ newline \n bell \u{7} é";
+        assert_eq!(parse(&Value::from(s).to_string()), Ok(Value::from(s)));
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(parse(r#""\ud83d\ude00""#), Ok(Value::from("😀")));
+        assert_eq!(parse(r#""\uD834\uDD1E!""#), Ok(Value::from("𝄞!")));
+        assert_eq!(parse(r#""\ud83dx""#), Ok(Value::from("\u{fffd}x")));
+        assert_eq!(parse(r#""\ud83d\u0041""#), Ok(Value::from("\u{fffd}A")));
+        assert_eq!(parse(r#""\ude00""#), Ok(Value::from("\u{fffd}")));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("0"), Ok(Value::Number(0.0)));
+        assert_eq!(parse("-12"), Ok(Value::Number(-12.0)));
+        assert_eq!(parse("3.25"), Ok(Value::Number(3.25)));
+        assert_eq!(parse("1e3"), Ok(Value::Number(1000.0)));
+        assert_eq!(parse("2.5E-1"), Ok(Value::Number(0.25)));
+        assert_eq!(parse(" 7 ").unwrap().as_i64(), Some(7));
+        assert_eq!(Value::from(42).to_string(), "42");
+    }
+
+    #[test]
+    fn nesting() {
+        let text = r#"{"seq": 1, "arguments": {"breakpoints": [{"line": 3}, {"line": 4}], "empty": {}, "none": []}, "ok": true, "x": null}"#;
+        let value = parse(text).unwrap();
+
+        assert_eq!(value.get("seq").as_i64(), Some(1));
+        assert_eq!(value.get("ok").as_bool(), Some(true));
+        assert_eq!(value.get("x"), &Value::Null);
+        assert_eq!(value.get("missing"), &Value::Null);
+
+        let breakpoints = value.get("arguments").get("breakpoints").as_array();
+        assert_eq!(breakpoints.len(), 2);
+        assert_eq!(breakpoints[1].get("line").as_i64(), Some(4));
+        assert_eq!(value.get("arguments").get("empty"), &Value::Object(Vec::new()));
+        assert_eq!(value.get("arguments").get("none"), &Value::Array(Vec::new()));
+
+        assert_eq!(parse(&value.to_string()), Ok(value));
+        assert_eq!(parse("[[[1]]]").unwrap().to_string(), "[[[1]]]");
+    }
+
+    #[test]
+    fn malformed() {
+        for text in [
+            "", " ", "nul", "tru", "[1,", "[1 2]", "{\"a\" 1}", "{\"a\": 1,}", "{a: 1}",
+            "\"open", "\"\\u12\"", "\"\\uzzzz\"", "1 2", "{} x", "-", "1.2.3", "@",
+        ] {
+            assert!(parse(text).is_err(), "{text:?} should not parse");
+        }
+    }
+}
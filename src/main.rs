@@ -1,43 +1,405 @@
+mod base64;
 mod chip;
+mod clip;
+mod command;
+mod config;
+mod dap;
 mod debug;
+mod debugger;
 mod decode;
+mod disasm;
+mod expr;
 mod font;
+mod frontend;
+mod gdb;
 mod get_line;
+mod gif;
+mod graphics;
 mod hex;
+mod image;
+mod interrupt;
+mod history;
+mod json;
+mod movie;
+mod palette;
+mod rewind;
+mod rng;
+mod screen;
+mod state;
+mod symbols;
+mod terminal;
+mod text_screen;
 mod timer;
+mod trace;
+mod trace_diff;
 mod types;
 
-use crate::chip::Chip;
+use crate::chip::{Chip, IMAGE_SCALE};
+use crate::clip::Clip;
+use crate::frontend::{Frontend, WindowScale, WindowSettings};
+use crate::graphics::Graphics;
+use crate::movie::Movie;
+use crate::palette::Palette;
+use crate::rng::{Algorithm, Rng};
+use crate::symbols::Symbols;
+use crate::terminal::Terminal;
+use crate::text_screen::Style;
+use crate::trace::Tracer;
+use crate::types::Address;
+
+const USAGE: &str = "usage: nn [--config file] [run] [--frames n] [display options] [rng options] [movie options] [symbol options] [trace options] rom_path
+       nn debug [--script file] [display options] [rng options] [movie options] [symbol options] [trace options] rom_path
+       nn gdb [--port port] [display options] [trace options] rom_path
+       nn dap [--scale n|fit] [--fullscreen] [--resizable] [--headless] [--theme name]
+              [--palette colours] [symbol options]
+       nn trace-diff [--context n] [--post] trace other_log
+       nn -d rom_path
+display options:
+       --scale n|fit             window scale from 1 to 32 (default 8), or the
+                                 largest whole scale that fits the screen
+       --fullscreen              a borderless window covering the screen, with the
+                                 display letterboxed in it; Esc closes it
+       --resizable               let the window be resized; the display keeps
+                                 its shape, with bars at the sides
+       --headless                run without a window, as fast as possible
+       --tui                     draw the display in the terminal instead of a window;
+                                 Esc quits
+       --tui-style blocks|braille|sixel|kitty
+                                 draw with characters (default blocks), or as
+                                 images where the terminal can show them
+       --tui-scale n             pixel size of images in the terminal (default 4)
+       --theme name              colours to draw in: mono (default), amber, green,
+                                 lcd, octo or paper
+       --palette colours         background and foreground colours as hex RGB,
+                                 e.g. 000000,ffffff, then optionally two more for
+                                 XO-CHIP's second plane and both planes
+       --capture file            record the display to an animated .gif, or to
+                                 numbered .png frames (file-00001.png, ...)
+trace options:
+       --trace file              write an execution trace to file
+       --trace-format text|bin   trace format (default text)
+       --trace-range start-end   only trace instructions in range, e.g. 0x200-0x2ff;
+                                 such traces cannot be given to trace-diff
+rng options:
+       --seed n                  seed the random number generator, decimal or 0x
+                                 hex up to 64 bits (default random, and printed)
+       --rng xorshift|vip        random number generator (default xorshift); vip
+                                 follows the COSMAC VIP interpreter's method
+       --vip-page file           the 256-byte page of the VIP interpreter that its
+                                 random numbers come from, needed for vip
+movie options:
+       --record file             record the keys pressed in each frame to file
+       --play file               play back keys recorded with --record
+symbol options:
+       --symbols file            load labels and source lines for traces and the debugger
+fit and --fullscreen find the screen through X11 (Xwayland under Wayland), and
+without it fall back to scale 8. Display options --scale, --fullscreen,
+--resizable, --theme and --palette can also be set in a config file, one
+\"name = value\" a line, by default ~/.config/nn/config.
+While a game runs, F12 in the window or Ctrl-C breaks into the debugger,
+F5 saves the state to rom_path.state, F9 loads it back, holding Backspace
+rewinds up to 30 seconds, F2 saves a screenshot to rom_path-n.png and F3
+starts or stops capturing a clip to rom_path-n.gif. --frames n stops after
+n frames; a headless run of a movie stops when the movie ends.";
+
+const DEFAULT_GDB_PORT: u16 = 1234;
+const DEFAULT_TUI_SCALE: usize = 4;
+
+enum Mode {
+    Run,
+    Debug,
+    Gdb,
+    Dap,
+    TraceDiff,
+}
+
+struct Options {
+    mode: Mode,
+    script: Option<String>,
+    frames: Option<u64>,
+    window: WindowSettings,
+    headless: bool,
+    tui: Option<(Style, Option<Graphics>)>,
+    tui_scale: usize,
+    palette: Palette,
+    capture: Option<String>,
+    symbols: Option<String>,
+    seed: Option<u64>,
+    rng: Algorithm,
+    vip_page: Option<String>,
+    record: Option<String>,
+    play: Option<String>,
+    port: u16,
+    trace: Option<String>,
+    trace_format: trace::Format,
+    trace_ranges: Vec<(Address, Address)>,
+    context: usize,
+    post: bool,
+    paths: Vec<String>,
+}
 
 fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            println!("{err}");
+            println!("{USAGE}");
+            return;
+        },
+    };
+
+    // Stdout belongs to the protocol, and the ROM comes with the launch request.
+    if let Mode::Dap = options.mode {
+        dap::serve(options.window, options.headless, options.palette, options.symbols);
+        return;
+    }
+
+    if let Mode::TraceDiff = options.mode {
+        let result = match &options.paths[..] {
+            [ours, theirs] => trace_diff::diff(ours, theirs, options.context, options.post),
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(err) = result {
+            println!("{err}");
+        }
+        return;
+    }
+
     println!("Hello, CHIP 8");
 
-    let args: Vec<String> = std::env::args().collect();
-    let mut debug = false;
-    let mut path = None;
+    let path = match &options.paths[..] {
+        [path] => path,
+        _ => {
+            println!("{USAGE}");
+            return;
+        },
+    };
+
+    let symbols = match &options.symbols {
+        Some(path) => match Symbols::load(path) {
+            Ok(symbols) => symbols,
+            Err(err) => {
+                println!("{err}");
+                return;
+            },
+        },
+        None => Symbols::default(),
+    };
 
-    for arg in args[1..].iter() {
-        if arg.eq("-d") {
-            debug = true;
-        } else {
-            path = Some(arg.clone());
+    let rom = open_rom(path);
+    let frontend = match options.tui {
+        Some((style, graphics)) => Frontend::Terminal(Box::new(Terminal::new(style, graphics, options.tui_scale))),
+        None if options.headless => Frontend::headless(),
+        None => Chip::window(options.window),
+    };
+    let mut chip = Chip::new(frontend);
+    chip.set_palette(options.palette);
+    chip.load_font();
+    chip.load_rom(rom.clone());
+    chip.set_rom_path(path);
+
+    if let Some(page_path) = &options.vip_page {
+        match rng::load_vip_page(page_path) {
+            Ok(page) => chip.set_vip_page(page),
+            Err(err) => {
+                println!("{err}");
+                return;
+            },
         }
     }
 
-    if path.is_none() {
-        println!("usage: nn [-d] rom_path");
+    // A movie brings its own seed.
+    let seed = options.seed.unwrap_or_else(rand::random);
+    if options.seed.is_none() && options.play.is_none() {
+        println!("Random seed {seed}; run with --seed {seed} to repeat");
+    }
+    if let Err(err) = chip.set_rng(Rng::new(seed, options.rng)) {
+        println!("{err}");
         return;
     }
 
-    let rom = open_rom(&path.unwrap());
+    if let Some(movie_path) = &options.record {
+        match Movie::record(movie_path, seed, options.rng, &rom) {
+            Ok(movie) => chip.set_movie(movie),
+            Err(err) => {
+                println!("{movie_path}: {err}");
+                return;
+            },
+        }
+    }
+
+    if let Some(movie_path) = &options.play {
+        match Movie::play(movie_path, &rom) {
+            Ok((movie, rng)) => {
+                if let Err(err) = chip.set_rng(rng) {
+                    println!("{movie_path}: {err}");
+                    return;
+                }
+                chip.set_movie(movie);
+            },
+            Err(err) => {
+                println!("{err}");
+                return;
+            },
+        }
+    }
 
-    if debug {
-        debug::debug(rom);
-    } else {
-        let mut chip = Chip::new();
-        chip.load_rom(rom);
-        chip.run();
+    if let Some(clip_path) = &options.capture {
+        let (width, height) = chip.display_size();
+        match Clip::create(clip_path, width, height, IMAGE_SCALE) {
+            Ok(clip) => chip.set_clip(clip),
+            Err(err) => {
+                println!("{err}");
+                return;
+            },
+        }
     }
+
+    if let Some(trace_path) = &options.trace {
+        match Tracer::new(trace_path, options.trace_format, options.trace_ranges.clone()) {
+            Ok(mut tracer) => {
+                tracer.set_labels(debugger::labels(chip.memory(), &symbols));
+                chip.set_tracer(tracer);
+            },
+            Err(err) => {
+                println!("{trace_path}: {err}");
+                return;
+            },
+        }
+    }
+
+    if let Mode::Run | Mode::Debug = options.mode {
+        interrupt::install();
+    }
+
+    match options.mode {
+        Mode::Run => {
+            if chip.run(options.frames) {
+                debug::debug(chip, None, symbols);
+            }
+        },
+        Mode::Debug => debug::debug(chip, options.script, symbols),
+        Mode::Gdb => gdb::serve(chip, symbols, options.port),
+        Mode::Dap | Mode::TraceDiff => (),
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Settings from the config file come first, for the command line to override.
+    let config = match args.iter().position(|arg| arg == "--config") {
+        Some(n) => Some(args.get(n + 1).cloned().ok_or("--config needs a value")?),
+        None => config::default_path(),
+    };
+    let args = match config {
+        Some(path) => [config::load(&path)?, args].concat(),
+        None => args,
+    };
+    let mut args = args.iter();
+    let mut options = Options {
+        mode: Mode::Run,
+        script: None,
+        frames: None,
+        window: WindowSettings::default(),
+        headless: false,
+        tui: None,
+        tui_scale: DEFAULT_TUI_SCALE,
+        palette: Palette::default(),
+        capture: None,
+        symbols: None,
+        seed: None,
+        rng: Algorithm::Xorshift,
+        vip_page: None,
+        record: None,
+        play: None,
+        port: DEFAULT_GDB_PORT,
+        trace: None,
+        trace_format: trace::Format::Text,
+        trace_ranges: Vec::new(),
+        context: 5,
+        post: false,
+        paths: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "run" if options.paths.is_empty() => (),
+            "debug" if options.paths.is_empty() => options.mode = Mode::Debug,
+            "gdb" if options.paths.is_empty() => options.mode = Mode::Gdb,
+            "dap" if options.paths.is_empty() => options.mode = Mode::Dap,
+            "trace-diff" if options.paths.is_empty() => options.mode = Mode::TraceDiff,
+            "-d" => options.mode = Mode::Debug,
+            "--script" => options.script = Some(value()?),
+            "--frames" => options.frames = Some(value()?.parse().map_err(|_| "bad frame count")?),
+            "--scale" => options.window.scale = WindowScale::parse(&value()?)?,
+            "--fullscreen" => options.window.fullscreen = true,
+            "--resizable" => options.window.resizable = true,
+            "--headless" => options.headless = true,
+            "--tui" => options.tui = Some(options.tui.unwrap_or((Style::Blocks, None))),
+            "--tui-style" => options.tui = Some(match value()?.as_str() {
+                "blocks" => (Style::Blocks, None),
+                "braille" => (Style::Braille, None),
+                "sixel" => (Style::Blocks, Some(Graphics::Sixel)),
+                "kitty" => (Style::Blocks, Some(Graphics::Kitty)),
+                style => return Err(format!("unknown tui style: {style}")),
+            }),
+            "--theme" => options.palette = Palette::theme(&value()?)?,
+            "--palette" => options.palette = Palette::parse(&value()?)?,
+            "--config" => { value()?; },
+            "--tui-scale" => options.tui_scale = match value()?.parse() {
+                Ok(scale @ 1 ..= 32) => scale,
+                _ => return Err("--tui-scale must be from 1 to 32".to_string()),
+            },
+            "--capture" => options.capture = Some(value()?),
+            "--symbols" => options.symbols = Some(value()?),
+            "--seed" => options.seed = Some(parse_seed(&value()?)?),
+            "--rng" => options.rng = Algorithm::parse(&value()?)?,
+            "--vip-page" => options.vip_page = Some(value()?),
+            "--record" => options.record = Some(value()?),
+            "--play" => options.play = Some(value()?),
+            "--port" => options.port = value()?.parse().map_err(|_| "bad port")?,
+            "--trace" => options.trace = Some(value()?),
+            "--trace-format" => options.trace_format = match value()?.as_str() {
+                "text" => trace::Format::Text,
+                "bin" | "binary" => trace::Format::Binary,
+                format => return Err(format!("unknown trace format: {format}")),
+            },
+            "--trace-range" => options.trace_ranges.push(trace::parse_range(&value()?)?),
+            "--context" => options.context = value()?.parse().map_err(|_| "bad context")?,
+            "--post" => options.post = true,
+            _ => options.paths.push(arg.clone()),
+        }
+    }
+
+    if options.script.is_some() && !matches!(options.mode, Mode::Debug) {
+        return Err("--script is only for debug mode".to_string());
+    }
+    if options.frames.is_some() && !matches!(options.mode, Mode::Run) {
+        return Err("--frames is only for run mode".to_string());
+    }
+    if options.tui.is_some() && matches!(options.mode, Mode::Dap) {
+        return Err("--tui cannot be used with dap, whose protocol uses the terminal".to_string());
+    }
+    if options.headless && options.tui.is_some() {
+        return Err("--headless and --tui cannot be used together".to_string());
+    }
+    if options.record.is_some() && options.play.is_some() {
+        return Err("--record and --play cannot be used together".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse_seed(s: &str) -> Result<u64, String> {
+    let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+
+    result.map_err(|_| format!("bad seed: {s}"))
 }
 
 fn open_rom(path: &str) -> Vec<u8> {
@@ -0,0 +1,150 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use crate::rng::{Algorithm, Rng};
+
+// Movies: the keypad held in each frame and the RNG seed, which is all it
+// takes to replay a session exactly.
+//
+// The file starts with MAGIC and a version byte, then the seed (u64), the
+// RNG algorithm (a byte, from version 2 on; version 1 movies use xorshift)
+// and a hash of the ROM (u64), then one keypad (u16, bit n set for key n
+// held) per frame. Multi-byte fields are little-endian.
+
+pub const MAGIC: &[u8; 4] = b"NNMV";
+pub const VERSION: u8 = 2;
+// The size of the header movies are recorded with.
+const HEADER_SIZE: u64 = 22;
+
+pub enum Movie {
+    Recording { out: BufWriter<File>, frames: usize },
+    Playing { frames: Vec<u16>, next: usize },
+}
+
+impl Movie {
+    pub fn record(path: &str, seed: u64, algorithm: Algorithm, rom: &[u8]) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&seed.to_le_bytes())?;
+        out.write_all(&[algorithm.to_byte()])?;
+        out.write_all(&hash(rom).to_le_bytes())?;
+
+        Ok(Movie::Recording { out, frames: 0 })
+    }
+
+    // Returns the movie and the RNG to play it with.
+    pub fn play(path: &str, rom: &[u8]) -> Result<(Self, Rng), String> {
+        let bytes = fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+
+        if !bytes.starts_with(MAGIC) {
+            return Err(format!("{path}: not a movie"));
+        }
+        let version = match bytes.get(MAGIC.len()) {
+            Some(version @ 1 ..= VERSION) => *version,
+            _ => return Err(format!("{path}: unsupported movie version")),
+        };
+        let header_size = if version == 1 { 21 } else { 22 };
+        if bytes.len() < header_size {
+            return Err(format!("{path}: truncated movie"));
+        }
+
+        let seed = u64::from_le_bytes(bytes[5 .. 13].try_into().unwrap());
+        let algorithm = match version {
+            1 => Algorithm::Xorshift,
+            _ => Algorithm::from_byte(bytes[13]).map_err(|err| format!("{path}: {err}"))?,
+        };
+        let rom_hash = u64::from_le_bytes(bytes[header_size - 8 .. header_size].try_into().unwrap());
+        if rom_hash != hash(rom) {
+            return Err(format!("{path}: recorded with a different ROM"));
+        }
+
+        let frames = bytes[header_size ..]
+            .chunks_exact(2)
+            .map(|keys| u16::from_le_bytes([keys[0], keys[1]]))
+            .collect();
+
+        Ok((Movie::Playing { frames, next: 0 }, Rng::new(seed, algorithm)))
+    }
+
+    // Returns the keypad for the next frame: the live one when recording,
+    // or the recorded one when playing. None means the movie is over.
+    pub fn frame(&mut self, live: u16) -> Option<u16> {
+        match self {
+            Movie::Recording { out, frames } => match out.write_all(&live.to_le_bytes()) {
+                Ok(()) => {
+                    *frames += 1;
+                    Some(live)
+                },
+                Err(err) => {
+                    println!("Recording stopped: {err}");
+                    None
+                },
+            },
+            Movie::Playing { frames, next } => {
+                let keys = frames.get(*next).copied();
+                *next += 1;
+                keys
+            },
+        }
+    }
+
+    // The number of frames recorded or played.
+    pub fn position(&self) -> usize {
+        match self {
+            Movie::Recording { frames, .. } => *frames,
+            Movie::Playing { next, .. } => *next,
+        }
+    }
+
+    // Goes back to an earlier position, dropping any frames recorded since.
+    pub fn seek(&mut self, position: usize) -> io::Result<()> {
+        match self {
+            Movie::Recording { out, frames } => {
+                if position != *frames {
+                    out.flush()?;
+                    let file = out.get_mut();
+                    file.set_len(HEADER_SIZE + 2 * position as u64)?;
+                    file.seek(SeekFrom::End(0))?;
+                    *frames = position;
+                }
+            },
+            Movie::Playing { next, .. } => *next = position,
+        }
+        Ok(())
+    }
+}
+
+// FNV-1a.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeking_back_drops_recorded_frames() {
+        let path = std::env::temp_dir().join(format!("nn-movie-{}.nnm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let rom = [0x12, 0x00];
+
+        let mut movie = Movie::record(path, 7, Algorithm::Xorshift, &rom).unwrap();
+        for keys in [1, 2, 3] {
+            movie.frame(keys);
+        }
+        movie.seek(1).unwrap();
+        assert_eq!(movie.position(), 1);
+        movie.frame(4);
+        drop(movie);
+
+        let (mut movie, _) = Movie::play(path, &rom).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!([movie.frame(0), movie.frame(0), movie.frame(0)], [Some(1), Some(4), None]);
+
+        movie.seek(1).unwrap();
+        assert_eq!(movie.frame(0), Some(4));
+    }
+}
@@ -0,0 +1,85 @@
+// The colours pixels are shown in. The display holds a colour number per
+// pixel: 0 for background and 1 for foreground, with 2 and 3 for the
+// second plane of XO-CHIP, drawn alone and overlapping the first.
+//
+// A palette is a theme name or a list of two or four colours as hex RGB,
+// e.g. "000000,ffffff". Given two, the XO colours are made from them.
+
+pub const THEMES: [(&str, [u32; 4]); 6] = [
+    ("mono", [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]),
+    ("amber", [0x1A0F00, 0xFFB000, 0xB36B00, 0x664000]),
+    ("green", [0x001400, 0x33FF66, 0x1FA040, 0x0F6020]),
+    ("lcd", [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F]),
+    ("octo", [0x996600, 0xFFCC00, 0xFF6600, 0x662200]),
+    ("paper", [0xF4F1E8, 0x222222, 0x8C3B3B, 0x3B5C8C]),
+];
+
+#[derive(Clone, Copy)]
+pub struct Palette {
+    colours: [u32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self { colours: THEMES[0].1 }
+    }
+}
+
+impl Palette {
+    pub fn theme(name: &str) -> Result<Self, String> {
+        match THEMES.iter().find(|(theme, _)| *theme == name) {
+            Some((_, colours)) => Ok(Self { colours: *colours }),
+            None => {
+                let names: Vec<&str> = THEMES.iter().map(|(theme, _)| *theme).collect();
+                Err(format!("unknown theme: {name} (themes: {})", names.join(", ")))
+            },
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let colours = spec
+            .split(',')
+            .map(|colour| parse_colour(colour.trim()))
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        match colours[..] {
+            [background, foreground] => Ok(Self {
+                colours: [background, foreground, mix(background, foreground, 2), mix(background, foreground, 1)],
+            }),
+            [a, b, c, d] => Ok(Self { colours: [a, b, c, d] }),
+            _ => Err(format!("a palette needs 2 or 4 colours: {spec}")),
+        }
+    }
+
+    pub fn background(&self) -> u32 {
+        self.colours[0]
+    }
+
+    pub fn foreground(&self) -> u32 {
+        self.colours[1]
+    }
+
+    // The RGB values of a display's colour numbers.
+    pub fn colours(&self, pixels: &[u8]) -> Vec<u32> {
+        pixels.iter().map(|pixel| self.colours[*pixel as usize & 3]).collect()
+    }
+}
+
+fn parse_colour(colour: &str) -> Result<u32, String> {
+    let hex = colour.trim_start_matches('#').trim_start_matches("0x");
+
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => Ok(rgb),
+        _ => Err(format!("bad colour: {colour}")),
+    }
+}
+
+// Mixes a and b, thirds of b out of 3.
+fn mix(a: u32, b: u32, thirds: u32) -> u32 {
+    let channel = |shift: u32| {
+        let (a, b) = ((a >> shift) & 0xFF, (b >> shift) & 0xFF);
+        ((a * (3 - thirds) + b * thirds) / 3) << shift
+    };
+
+    channel(16) | channel(8) | channel(0)
+}
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use crate::state::State;
+use crate::types::*;
+
+// The last few seconds of play, one snapshot per frame, oldest dropped first.
+//
+// Memory is kept as its difference from a base image, usually the memory as
+// loaded. Games change little of it, so the difference is mostly zeros and
+// stored as runs: a zero count and a literal count (both u16, little-endian)
+// followed by the literal bytes.
+
+pub struct Rewind {
+    base: Vec<Byte>,
+    snapshots: VecDeque<State>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(base: &[Byte], capacity: usize) -> Self {
+        Self { base: base.to_vec(), snapshots: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, mut state: State) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        state.memory = compress(&self.base, &state.memory);
+        self.snapshots.push_back(state);
+    }
+
+    pub fn pop(&mut self) -> Option<State> {
+        let mut state = self.snapshots.pop_back()?;
+        state.memory = decompress(&self.base, &state.memory);
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+fn compress(base: &[Byte], memory: &[Byte]) -> Vec<Byte> {
+    let diff: Vec<Byte> = base.iter().zip(memory).map(|(a, b)| a ^ b).collect();
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < diff.len() {
+        let zeros = diff[pos ..].iter().take(u16::MAX as usize).take_while(|b| **b == 0).count();
+        pos += zeros;
+        let literals = diff[pos ..].iter().take(u16::MAX as usize).take_while(|b| **b != 0).count();
+
+        out.extend_from_slice(&(zeros as u16).to_le_bytes());
+        out.extend_from_slice(&(literals as u16).to_le_bytes());
+        out.extend_from_slice(&diff[pos .. pos + literals]);
+        pos += literals;
+    }
+
+    out
+}
+
+fn decompress(base: &[Byte], runs: &[Byte]) -> Vec<Byte> {
+    let mut memory = base.to_vec();
+    let mut addr = 0;
+    let mut pos = 0;
+
+    while pos + 4 <= runs.len() {
+        let zeros = u16::from_le_bytes([runs[pos], runs[pos + 1]]) as usize;
+        let literals = u16::from_le_bytes([runs[pos + 2], runs[pos + 3]]) as usize;
+        pos += 4;
+        addr += zeros;
+
+        for byte in &runs[pos .. pos + literals] {
+            memory[addr] ^= byte;
+            addr += 1;
+        }
+        pos += literals;
+    }
+
+    memory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[Byte], memory: &[Byte]) -> Vec<Byte> {
+        let runs = compress(base, memory);
+        assert_eq!(decompress(base, &runs), memory);
+        runs
+    }
+
+    #[test]
+    fn empty_delta() {
+        let base: Vec<Byte> = (0 .. 4096).map(|n| n as Byte).collect();
+        assert_eq!(round_trip(&base, &base), [0x00, 0x10, 0, 0]);
+        assert_eq!(round_trip(&[], &[]), []);
+    }
+
+    #[test]
+    fn all_changes() {
+        let runs = round_trip(&[0; 4096], &[0xFF; 4096]);
+        assert_eq!(runs[.. 4], [0, 0, 0x00, 0x10]);
+        assert_eq!(runs[4 ..], [0xFF; 4096]);
+    }
+
+    #[test]
+    fn long_runs_split() {
+        let base = vec![0; 70000];
+        assert_eq!(round_trip(&base, &base), [0xFF, 0xFF, 0, 0, 0x71, 0x11, 0, 0]);
+
+        let runs = round_trip(&base, &[1; 70000]);
+        assert_eq!(runs.len(), 4 + 65535 + 4 + 4465);
+        assert_eq!(runs[.. 4], [0, 0, 0xFF, 0xFF]);
+        assert_eq!(runs[4 + 65535 .. 8 + 65535], [0, 0, 0x71, 0x11]);
+    }
+
+    #[test]
+    fn mixed_runs() {
+        let base: Vec<Byte> = (0 .. 4096).map(|n| (n * 7) as Byte).collect();
+        let mut memory = base.clone();
+        memory[0x200 .. 0x204].copy_from_slice(&[1, 2, 3, 4]);
+        memory[0x300] ^= 0x80;
+        memory[4095] ^= 1;
+
+        let runs = round_trip(&base, &memory);
+        assert_eq!(runs[.. 4], [0x00, 0x02, 4, 0]);
+        assert_eq!(runs.len(), 3 * 4 + 4 + 1 + 1);
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs;
+
+use crate::types::*;
+
+// The random number generator behind CXNN, owned by the chip so that it can
+// be seeded, and saved and restored along with everything else.
+//
+// Xorshift is the default. Vip follows the scheme of the COSMAC VIP
+// interpreter: a 16-bit value that the display interrupt counts up once a
+// frame and each CXNN counts up once more, its low byte indexing a page of
+// the interpreter's own code whose byte is added into its high byte, which
+// is the result. The interpreter is RCA's and not included here, so the
+// page is read from a 256-byte dump of it given with --vip-page.
+
+pub const VIP_PAGE_SIZE: usize = 256;
+
+pub type VipPage = [Byte; VIP_PAGE_SIZE];
+
+pub fn load_vip_page(path: &str) -> Result<VipPage, String> {
+    let bytes = fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+    bytes.try_into().map_err(|bytes: Vec<u8>| format!("{path}: a VIP page is {VIP_PAGE_SIZE} bytes, not {}", bytes.len()))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Xorshift,
+    Vip,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "xorshift" => Ok(Algorithm::Xorshift),
+            "vip" => Ok(Algorithm::Vip),
+            _ => Err(format!("unknown random number generator: {name}")),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Algorithm::Xorshift => 0,
+            Algorithm::Vip => 1,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, String> {
+        match byte {
+            0 => Ok(Algorithm::Xorshift),
+            1 => Ok(Algorithm::Vip),
+            _ => Err(format!("unknown random number generator: {byte}")),
+        }
+    }
+}
+
+pub struct Rng {
+    algorithm: Algorithm,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64, algorithm: Algorithm) -> Self {
+        let state = match algorithm {
+            Algorithm::Xorshift => {
+                // Xorshift never leaves a zero state, so mix the seed first.
+                let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+                z ^= z >> 31;
+                if z == 0 { 1 } else { z }
+            },
+            Algorithm::Vip => seed & 0xFFFF,
+        };
+
+        Self { algorithm, state }
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random(), Algorithm::Xorshift)
+    }
+
+    // The Vip algorithm needs the interpreter page; without it, it adds nothing.
+    pub fn next_byte(&mut self, vip_page: Option<&VipPage>) -> u8 {
+        match self.algorithm {
+            Algorithm::Xorshift => {
+                self.state ^= self.state << 13;
+                self.state ^= self.state >> 7;
+                self.state ^= self.state << 17;
+                (self.state >> 32) as u8
+            },
+            Algorithm::Vip => {
+                let r = (self.state as u16).wrapping_add(1);
+                let high = ((r >> 8) as u8).wrapping_add(vip_page.map_or(0, |page| page[(r & 0xFF) as usize]));
+                self.state = ((high as u64) << 8) | (r & 0xFF) as u64;
+                high
+            },
+        }
+    }
+
+    // Called once a frame.
+    pub fn tick(&mut self) {
+        if self.algorithm == Algorithm::Vip {
+            self.state = (self.state + 1) & 0xFFFF;
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn restore(&mut self, algorithm: Algorithm, state: u64) {
+        self.algorithm = algorithm;
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vip() {
+        // A page counting down from ff, so byte n is ff - n.
+        let page: VipPage = std::array::from_fn(|n| 0xFF - n as u8);
+        let mut rng = Rng::new(0x12FE, Algorithm::Vip);
+
+        // 12fe + 1 = 12ff; 12 + page[ff] = 12 + 00 = 12.
+        assert_eq!(rng.next_byte(Some(&page)), 0x12);
+        assert_eq!(rng.state(), 0x12FF);
+        // 12ff + 1 = 1300; 13 + page[00] = 13 + ff = 12.
+        assert_eq!(rng.next_byte(Some(&page)), 0x12);
+        assert_eq!(rng.state(), 0x1200);
+        // A frame counts up once, then 1201 + 1 = 1202; 12 + fd = 0f.
+        rng.tick();
+        assert_eq!(rng.next_byte(Some(&page)), 0x0F);
+        assert_eq!(rng.state(), 0x0F02);
+
+        let mut rng = Rng::new(0xFFFF, Algorithm::Vip);
+        rng.tick();
+        assert_eq!(rng.state(), 0);
+    }
+
+    #[test]
+    fn seeds_repeat() {
+        for algorithm in [Algorithm::Xorshift, Algorithm::Vip] {
+            let page = [0x5A; VIP_PAGE_SIZE];
+            let run = |seed| {
+                let mut rng = Rng::new(seed, algorithm);
+                (0 .. 16).map(|_| rng.next_byte(Some(&page))).collect::<Vec<u8>>()
+            };
+            assert_eq!(run(u64::MAX - 1), run(u64::MAX - 1));
+        }
+        assert_ne!(Rng::new(1, Algorithm::Xorshift).state(), Rng::new(1 << 40, Algorithm::Xorshift).state());
+    }
+}
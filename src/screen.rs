@@ -0,0 +1,62 @@
+use std::ptr;
+use std::slice;
+
+use x11_dl::xlib::{Display, Xlib};
+use x11_dl::xrandr::Xrandr;
+
+// The screen that windows are fitted to: the primary monitor as XRandR
+// reports it, or the whole X screen without XRandR. minifb cannot say, and
+// its own fitting only picks powers of two. There is no screen without an X
+// display, as under Wayland without Xwayland.
+
+pub struct Screen {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+}
+
+pub fn primary() -> Option<Screen> {
+    let xlib = Xlib::open().ok()?;
+
+    unsafe {
+        let display = (xlib.XOpenDisplay)(ptr::null());
+        if display.is_null() {
+            return None;
+        }
+
+        let screen = monitor(&xlib, display).unwrap_or_else(|| {
+            let n = (xlib.XDefaultScreen)(display);
+            Screen {
+                x: 0,
+                y: 0,
+                width: (xlib.XDisplayWidth)(display, n).max(0) as usize,
+                height: (xlib.XDisplayHeight)(display, n).max(0) as usize,
+            }
+        });
+
+        (xlib.XCloseDisplay)(display);
+        Some(screen)
+    }
+}
+
+unsafe fn monitor(xlib: &Xlib, display: *mut Display) -> Option<Screen> {
+    let xrandr = Xrandr::open().ok()?;
+    let mut count = 0;
+
+    let monitors = (xrandr.XRRGetMonitors)(display, (xlib.XDefaultRootWindow)(display), 1, &mut count);
+    if monitors.is_null() {
+        return None;
+    }
+
+    let list = slice::from_raw_parts(monitors, count.max(0) as usize);
+    let screen = list.iter().find(|monitor| monitor.primary != 0).or(list.first()).map(|monitor| Screen {
+        x: monitor.x as isize,
+        y: monitor.y as isize,
+        width: monitor.width.max(0) as usize,
+        height: monitor.height.max(0) as usize,
+    });
+
+    (xrandr.XRRFreeMonitors)(monitors);
+    screen
+}
@@ -0,0 +1,50 @@
+use strict;
+use warnings;
+use feature 'say';
+use Getopt::Std;
+use IO::Socket::INET;
+
+# Sends GDB remote protocol packets to `nn gdb` and prints the replies.
+#
+#   perl rsp-client.pl -p 1234 '?' g 'm200,10' 'Z0,204,2' c
+#
+# Packets are read from stdin, one per line, when none are given.
+
+my %opts;
+getopts('p:', \%opts);
+
+my $sock = IO::Socket::INET->new(
+	PeerAddr => '127.0.0.1',
+	PeerPort => $opts{p} // 1234,
+	Proto => 'tcp',
+) or die "connect: $!\n";
+
+sub send_packet {
+	my $data = shift;
+	my $sum = 0;
+	$sum += ord for split //, $data;
+	printf $sock '$%s#%02x', $data, $sum % 256;
+}
+
+sub read_reply {
+	my ($c, $data) = ('', '');
+	while (read($sock, $c, 1)) {
+		last if $c eq '$';
+	}
+	while (read($sock, $c, 1)) {
+		last if $c eq '#';
+		$data .= $c;
+	}
+	read($sock, $c, 2);
+	print $sock '+';
+	return $data;
+}
+
+my @packets = @ARGV ? @ARGV : map { chomp; $_ } <STDIN>;
+
+for my $packet (@packets) {
+	send_packet($packet);
+	read($sock, my $ack, 1);
+	last if $packet eq 'k';
+	say "$packet -> ", read_reply();
+}
@@ -0,0 +1,203 @@
+use std::fs;
+
+use crate::rng::Algorithm;
+use crate::types::*;
+
+// Save states: a snapshot of everything that determines how a chip runs on.
+//
+// The file starts with MAGIC and a version byte, followed by PC, I, V0-VF,
+// the call stack (a depth byte, then each address), the delay and sound
+// timers, the keypad (bit n set for key n held), the display width and
+// height with one bit per pixel row by row, the RNG state and algorithm
+// (version 2 on; version 1 states use xorshift), the cycle count and finally
+// the memory size and contents. Multi-byte fields are little-endian.
+
+pub const MAGIC: &[u8; 4] = b"NNST";
+pub const VERSION: u8 = 2;
+
+#[derive(Clone, PartialEq)]
+pub struct State {
+    pub pc: Address,
+    pub i: Address,
+    pub v: [Byte; 16],
+    pub stack: Vec<Address>,
+    pub delay: u8,
+    pub sound: u8,
+    pub keypad: u16,
+    pub width: usize,
+    pub height: usize,
+    pub display: Vec<bool>,
+    pub rng: u64,
+    pub rng_algorithm: Algorithm,
+    pub cycles: u64,
+    pub memory: Vec<Byte>,
+}
+
+impl State {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let depth = u8::try_from(self.stack.len()).map_err(|_| format!("call stack too deep to save: {}", self.stack.len()))?;
+        let mut out = Vec::new();
+
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.extend_from_slice(&self.v);
+        out.push(depth);
+        for addr in self.stack.iter() {
+            out.extend_from_slice(&addr.to_le_bytes());
+        }
+        out.push(self.delay);
+        out.push(self.sound);
+        out.extend_from_slice(&self.keypad.to_le_bytes());
+        out.push(self.width as u8);
+        out.push(self.height as u8);
+        for pixels in self.display.chunks(8) {
+            out.push(pixels.iter().enumerate().fold(0, |byte, (n, on)| byte | (*on as u8) << (7 - n)));
+        }
+        out.extend_from_slice(&self.rng.to_le_bytes());
+        out.push(self.rng_algorithm.to_byte());
+        out.extend_from_slice(&self.cycles.to_le_bytes());
+        out.extend_from_slice(&(self.memory.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.memory);
+
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.starts_with(MAGIC) {
+            return Err("not a save state".to_string());
+        }
+        let version = match bytes.get(MAGIC.len()) {
+            Some(version @ 1 ..= VERSION) => *version,
+            _ => return Err("unsupported save state version".to_string()),
+        };
+
+        let mut pos = MAGIC.len() + 1;
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            let field = bytes.get(pos .. pos + n).ok_or("truncated save state")?;
+            pos += n;
+            Ok(field)
+        };
+        let word = |b: &[u8]| u16::from_le_bytes([b[0], b[1]]);
+        let long = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap());
+
+        let pc = word(take(2)?);
+        let i = word(take(2)?);
+        let v = take(16)?.try_into().unwrap();
+        let depth = take(1)?[0] as usize;
+        let stack = take(2 * depth)?.chunks(2).map(word).collect();
+        let delay = take(1)?[0];
+        let sound = take(1)?[0];
+        let keypad = word(take(2)?);
+        let width = take(1)?[0] as usize;
+        let height = take(1)?[0] as usize;
+        let display = take((width * height).div_ceil(8))?
+            .iter()
+            .flat_map(|byte| (0 .. 8).map(move |n| byte & 0x80 >> n != 0))
+            .take(width * height)
+            .collect();
+        let rng = long(take(8)?);
+        let rng_algorithm = match version {
+            1 => Algorithm::Xorshift,
+            _ => Algorithm::from_byte(take(1)?[0])?,
+        };
+        let cycles = long(take(8)?);
+        let size = word(take(2)?) as usize;
+        let memory = take(size)?.to_vec();
+
+        Ok(Self { pc, i, v, stack, delay, sound, keypad, width, height, display, rng, rng_algorithm, cycles, memory })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes()?).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::from_bytes(&bytes).map_err(|err| format!("{path}: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Chip;
+    use crate::frontend::Frontend;
+    use crate::rng::Rng;
+
+    // Draws a random column of digits down the display, with the delay timer
+    // set from the random number and a call and return each time round.
+    const ROM: [u8; 18] = [
+        0xC0, 0xFF, 0xA0, 0x00, 0xD0, 0x15, 0x71, 0x01, 0xF0, 0x15,
+        0x22, 0x10, 0x12, 0x00, 0x00, 0x00, 0x00, 0xEE,
+    ];
+
+    fn chip() -> Chip {
+        let mut chip = Chip::new(Frontend::headless());
+        chip.set_rng(Rng::new(1234, Algorithm::Xorshift)).unwrap();
+        chip.load_font();
+        chip.load_rom(ROM.to_vec());
+        chip
+    }
+
+    fn run(chip: &mut Chip, steps: usize) {
+        for _ in 0 .. steps {
+            chip.step();
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut chip = chip();
+        run(&mut chip, 110);
+        let state = chip.state();
+
+        assert_eq!(state.stack.len(), 1);
+        assert!(state.display.iter().any(|on| *on));
+        assert!(State::from_bytes(&state.to_bytes().unwrap()).unwrap() == state);
+    }
+
+    #[test]
+    fn bad_input_is_rejected() {
+        let bytes = chip().state().to_bytes().unwrap();
+
+        for len in 0 .. bytes.len() {
+            assert!(State::from_bytes(&bytes[.. len]).is_err(), "{len} bytes should not load");
+        }
+
+        let mut wrong = bytes.clone();
+        wrong[0] = b'X';
+        assert_eq!(State::from_bytes(&wrong).err(), Some("not a save state".to_string()));
+
+        for version in [0, VERSION + 1] {
+            let mut wrong = bytes.clone();
+            wrong[MAGIC.len()] = version;
+            assert_eq!(State::from_bytes(&wrong).err(), Some("unsupported save state version".to_string()));
+        }
+    }
+
+    #[test]
+    fn deep_stacks_are_rejected() {
+        let mut state = chip().state();
+
+        state.stack = vec![0x200; 255];
+        assert!(state.to_bytes().is_ok());
+        state.stack.push(0x200);
+        assert_eq!(state.to_bytes().err(), Some("call stack too deep to save: 256".to_string()));
+    }
+
+    #[test]
+    fn restoring_replays_the_same() {
+        let mut chip = chip();
+        run(&mut chip, 100);
+        let saved = chip.state().to_bytes().unwrap();
+
+        run(&mut chip, 250);
+        let first = chip.state();
+
+        chip.restore(&State::from_bytes(&saved).unwrap()).unwrap();
+        run(&mut chip, 250);
+        assert!(chip.state() == first);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::disasm::Labels;
+use crate::expr;
+use crate::types::*;
+
+// Symbol files map labels to addresses and, optionally, addresses to source
+// lines. Each line is an address followed by a label or a file:line, e.g.
+//
+//   0x200 main
+//   0x234 draw_player
+//   0x234 game.8o:40
+//
+// Blank lines and lines starting with # are ignored. Source files are looked
+// up relative to the symbol file, and shown when found.
+
+#[derive(Clone, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Default)]
+pub struct Symbols {
+    labels: Labels,
+    addresses: HashMap<String, Address>,
+    lines: BTreeMap<Address, Location>,
+    sources: HashMap<String, Vec<String>>,
+    // Where source files are, relative to.
+    dir: PathBuf,
+}
+
+impl Symbols {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let mut symbols = Self { dir: dir.to_path_buf(), ..Self::default() };
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = || format!("{path}:{}: bad symbol: {line}", n + 1);
+            let (addr, name) = line.split_once(char::is_whitespace).ok_or_else(bad_line)?;
+            let addr = expr::parse_number(addr).map_err(|_| bad_line())? as Address;
+            let name = name.trim();
+
+            match name.rsplit_once(':') {
+                Some((file, line)) => {
+                    let line = line.parse().map_err(|_| bad_line())?;
+                    symbols.lines.insert(addr, Location { file: file.to_string(), line });
+                },
+                None => {
+                    symbols.labels.insert(addr, name.to_string());
+                    symbols.addresses.insert(name.to_string(), addr);
+                },
+            }
+        }
+
+        for location in symbols.lines.values() {
+            if symbols.sources.contains_key(&location.file) {
+                continue;
+            }
+            if let Ok(source) = fs::read_to_string(dir.join(&location.file)) {
+                symbols.sources.insert(location.file.clone(), source.lines().map(str::to_string).collect());
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    pub fn address(&self, label: &str) -> Option<Address> {
+        self.addresses.get(label).copied()
+    }
+
+    // Names addr relative to the nearest label at or before it, e.g. main+4.
+    pub fn describe(&self, addr: Address) -> Option<String> {
+        let (start, label) = self.labels.iter().filter(|(a, _)| **a <= addr).max_by_key(|(a, _)| **a)?;
+
+        Some(match addr - start {
+            0 => label.clone(),
+            offset => format!("{label}+{offset}"),
+        })
+    }
+
+    pub fn has_lines(&self) -> bool {
+        !self.lines.is_empty()
+    }
+
+    // The source line addr belongs to.
+    pub fn location(&self, addr: Address) -> Option<&Location> {
+        self.lines.range(..= addr).next_back().map(|(_, location)| location)
+    }
+
+    // The source line starting exactly at addr.
+    pub fn line_start(&self, addr: Address) -> Option<&Location> {
+        self.lines.get(&addr)
+    }
+
+    // The first address of the code on the given line of a source file, or
+    // failing that the next line with code, and that line. The file matches
+    // locations naming it or the end of its path.
+    pub fn line_address(&self, file: &str, line: usize) -> Option<(Address, usize)> {
+        self.lines
+            .iter()
+            .filter(|(_, location)| location.line >= line && Path::new(file).ends_with(&location.file))
+            .min_by_key(|(addr, location)| (location.line, **addr))
+            .map(|(addr, location)| (*addr, location.line))
+    }
+
+    pub fn path(&self, location: &Location) -> PathBuf {
+        self.dir.join(&location.file)
+    }
+
+    pub fn source(&self, location: &Location) -> Option<&str> {
+        let lines = self.sources.get(&location.file)?;
+        lines.get(location.line.checked_sub(1)?).map(String::as_str)
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
@@ -0,0 +1,285 @@
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, queue, style, terminal};
+
+use crate::chip::DISPLAY_WIDTH;
+use crate::frontend::Hotkey;
+use crate::graphics::Graphics;
+use crate::image::Image;
+use crate::palette::Palette;
+use crate::text_screen::{self, Style};
+
+// A frontend drawing the display as text in the terminal, for working over
+// SSH without X. Only cells that changed since the last frame are redrawn.
+// Given graphics, frames are drawn as images at an integer scale instead,
+// falling back to text if the terminal cannot show them.
+//
+// Terminals send key presses and repeats but usually not releases, so a key
+// counts as held until a while after it was last seen: long enough after the
+// first press to reach the terminal's key repeat, and a little longer than
+// the repeat rate after that. Terminals that can report releases (the kitty
+// keyboard protocol) are asked to.
+//
+// The keypad is on the same keys as in the window. F2, F3, F5, F9 and
+// Backspace work as there, Ctrl-C breaks into the debugger and Esc quits.
+
+const KEYPAD: &str = "x123qweasdzc4rfv";
+const FIRST_TIMEOUT: Duration = Duration::from_millis(500);
+const REPEAT_TIMEOUT: Duration = Duration::from_millis(100);
+const FRAME: Duration = Duration::from_micros(16600);
+
+// Slots for held keys: the keypad, then the rewind key.
+const REWIND_SLOT: usize = 16;
+
+pub struct Terminal {
+    out: Stdout,
+    style: Style,
+    graphics: Option<Graphics>,
+    scale: usize,
+    // Whether the terminal has been asked if it can show the graphics.
+    asked: bool,
+    active: bool,
+    // Whether the terminal reports key releases, once asked.
+    releases: Option<bool>,
+    open: bool,
+    // The characters on screen, or empty when it needs redrawing in full.
+    cells: Vec<Vec<char>>,
+    // The last frame drawn as an image, or empty.
+    shown: Vec<u8>,
+    // When each key was last seen, and whether it has repeated since.
+    held: [Option<(Instant, bool)>; 17],
+    pressed: Vec<Hotkey>,
+    next_frame: Instant,
+}
+
+impl Terminal {
+    pub fn new(style: Style, graphics: Option<Graphics>, scale: usize) -> Self {
+        Self {
+            out: io::stdout(),
+            style,
+            graphics,
+            scale,
+            asked: false,
+            active: false,
+            releases: None,
+            open: true,
+            cells: Vec::new(),
+            shown: Vec::new(),
+            held: [None; 17],
+            pressed: Vec::new(),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    // Draws a frame and reads the keys, at most 60 times a second.
+    pub fn present(&mut self, pixels: &[u8], palette: &Palette, width: usize, height: usize) {
+        let now = Instant::now();
+        if now < self.next_frame {
+            std::thread::sleep(self.next_frame - now);
+        }
+        self.next_frame = self.next_frame.max(now) + FRAME;
+
+        if let Err(err) = self.resume().and_then(|()| self.draw(pixels, palette, width, height)) {
+            self.suspend();
+            println!("Terminal: {err}");
+            self.open = false;
+            return;
+        }
+        self.read_events();
+    }
+
+    pub fn keypad(&self) -> u16 {
+        (0 .. 16)
+            .filter(|key| self.is_held(*key))
+            .fold(0, |keypad, key| keypad | 1 << key)
+    }
+
+    pub fn pressed(&mut self, hotkey: Hotkey) -> bool {
+        let len = self.pressed.len();
+        self.pressed.retain(|pressed| *pressed != hotkey);
+        self.pressed.len() != len
+    }
+
+    pub fn held(&self, hotkey: Hotkey) -> bool {
+        matches!(hotkey, Hotkey::Rewind) && self.is_held(REWIND_SLOT)
+    }
+
+    // Gives the terminal back, to the debugger or on exit. The next frame
+    // takes it again.
+    pub fn suspend(&mut self) {
+        if !self.active {
+            return;
+        }
+        self.active = false;
+
+        if self.releases == Some(true) {
+            let _ = queue!(self.out, PopKeyboardEnhancementFlags);
+        }
+        if let Some(graphics) = self.graphics {
+            let _ = queue!(self.out, style::Print(graphics.clear()));
+        }
+        let _ = queue!(self.out, style::ResetColor, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = self.out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        if self.active {
+            return Ok(());
+        }
+
+        terminal::enable_raw_mode()?;
+        self.active = true;
+
+        if let (Some(graphics), false) = (self.graphics, self.asked) {
+            self.asked = true;
+            if !graphics.supported(&mut self.out)? {
+                write!(self.out, "The terminal cannot show these graphics, using text instead\r\n")?;
+                self.graphics = None;
+            }
+        }
+        queue!(self.out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let releases = *self.releases.get_or_insert_with(|| terminal::supports_keyboard_enhancement().unwrap_or(false));
+        if releases {
+            let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            queue!(self.out, PushKeyboardEnhancementFlags(flags))?;
+        }
+
+        self.cells.clear();
+        self.shown.clear();
+        self.held = [None; 17];
+        Ok(())
+    }
+
+    fn draw(&mut self, pixels: &[u8], palette: &Palette, width: usize, height: usize) -> io::Result<()> {
+        if let Some(graphics) = self.graphics {
+            return self.draw_image(graphics, pixels, palette, width, height);
+        }
+
+        // Text has only two colours, so any plane counts as foreground.
+        let rgb = |colour: u32| style::Color::Rgb { r: (colour >> 16) as u8, g: (colour >> 8) as u8, b: colour as u8 };
+        queue!(self.out, style::SetColors(style::Colors::new(rgb(palette.foreground()), rgb(palette.background()))))?;
+
+        let rows: Vec<Vec<char>> = text_screen::render(width, height, self.style, |x, y| pixels[y * width + x] != 0, |_, _| false)
+            .into_iter()
+            .map(|row| row.chars().collect())
+            .collect();
+
+        // A new size, as on switching resolution, starts again.
+        let sizes = |cells: &Vec<Vec<char>>| (cells.len(), cells.first().map_or(0, |row| row.len()));
+        if sizes(&self.cells) != sizes(&rows) {
+            queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+            self.cells = rows.iter().map(|row| vec!['\0'; row.len()]).collect();
+        }
+
+        for (y, (row, old)) in rows.iter().zip(self.cells.iter_mut()).enumerate() {
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] == old[x] {
+                    x += 1;
+                    continue;
+                }
+
+                let start = x;
+                while x < row.len() && row[x] != old[x] {
+                    x += 1;
+                }
+                let run: String = row[start .. x].iter().collect();
+                queue!(self.out, cursor::MoveTo(start as u16, y as u16), style::Print(run))?;
+                old[start .. x].copy_from_slice(&row[start .. x]);
+            }
+        }
+
+        self.out.flush()
+    }
+
+    fn draw_image(&mut self, graphics: Graphics, pixels: &[u8], palette: &Palette, width: usize, height: usize) -> io::Result<()> {
+        if pixels == self.shown {
+            return Ok(());
+        }
+        if pixels.len() != self.shown.len() {
+            queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+        }
+        self.shown = pixels.to_vec();
+
+        // The scale is for the low-res display; hi-res is drawn at half of it
+        // to keep the image the same size.
+        let scale = (self.scale * DISPLAY_WIDTH / width).max(1);
+        let image = Image::new(&palette.colours(pixels), width, height).scaled(scale);
+        queue!(self.out, cursor::MoveTo(0, 0), style::Print(graphics.encode(&image)))?;
+        self.out.flush()
+    }
+
+    fn read_events(&mut self) {
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            match event::read() {
+                Ok(Event::Key(key)) => self.key(key),
+                Ok(Event::Resize(..)) => {
+                    self.cells.clear();
+                    self.shown.clear();
+                },
+                Ok(_) => (),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        let slot = match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => KEYPAD.find(c.to_ascii_lowercase()),
+            KeyCode::Backspace => Some(REWIND_SLOT),
+            _ => None,
+        };
+
+        match (slot, key.kind) {
+            (Some(slot), KeyEventKind::Release) => self.held[slot] = None,
+            (Some(slot), kind) => {
+                let repeated = kind == KeyEventKind::Repeat || self.is_held(slot);
+                self.held[slot] = Some((Instant::now(), repeated));
+            },
+            (None, KeyEventKind::Release) => (),
+            (None, _) => self.hotkey(key.code),
+        }
+    }
+
+    fn hotkey(&mut self, code: KeyCode) {
+        match code {
+            // Plain c is a keypad key, so this is Ctrl-C.
+            KeyCode::Char('c') => self.pressed.push(Hotkey::Break),
+            KeyCode::Esc => self.open = false,
+            KeyCode::F(2) => self.pressed.push(Hotkey::Screenshot),
+            KeyCode::F(3) => self.pressed.push(Hotkey::Capture),
+            KeyCode::F(5) => self.pressed.push(Hotkey::Save),
+            KeyCode::F(9) => self.pressed.push(Hotkey::Load),
+            KeyCode::F(12) => self.pressed.push(Hotkey::Break),
+            _ => (),
+        }
+    }
+
+    fn is_held(&self, slot: usize) -> bool {
+        match self.held[slot] {
+            Some(_) if self.releases == Some(true) => true,
+            Some((seen, repeated)) => {
+                let timeout = if repeated { REPEAT_TIMEOUT } else { FIRST_TIMEOUT };
+                seen.elapsed() < timeout
+            },
+            None => false,
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.suspend();
+    }
+}
@@ -0,0 +1,87 @@
+// Draws a display as text, for terminals without a window.
+//
+// Blocks uses half block characters, one per 1x2 pixels, and braille one
+// character per 2x4 pixels. Cells holding a highlighted pixel are shown in
+// colour.
+
+// Yellow on red, so that changed cells show even when all their pixels are off.
+const HIGHLIGHT: &str = "\x1b[93;41m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    Blocks,
+    Braille,
+}
+
+impl Style {
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Style::Blocks => (1, 2),
+            Style::Braille => (2, 4),
+        }
+    }
+}
+
+// Returns the rows of text for a width x height display.
+pub fn render<F, G>(width: usize, height: usize, style: Style, lit: F, highlight: G) -> Vec<String>
+where
+    F: Fn(usize, usize) -> bool,
+    G: Fn(usize, usize) -> bool,
+{
+    let (cell_width, cell_height) = style.cell_size();
+    let mut rows = Vec::new();
+
+    for top in (0 .. height).step_by(cell_height) {
+        let mut row = String::new();
+
+        for left in (0 .. width).step_by(cell_width) {
+            let inside = |dx: usize, dy: usize| left + dx < width && top + dy < height;
+            let pixel = |dx: usize, dy: usize| inside(dx, dy) && lit(left + dx, top + dy);
+            let marked = (0 .. cell_height)
+                .any(|dy| (0 .. cell_width).any(|dx| inside(dx, dy) && highlight(left + dx, top + dy)));
+
+            let c = match style {
+                Style::Blocks => block(pixel(0, 0), pixel(0, 1)),
+                Style::Braille => braille(pixel),
+            };
+
+            if marked {
+                row.push_str(HIGHLIGHT);
+                row.push(c);
+                row.push_str(RESET);
+            } else {
+                row.push(c);
+            }
+        }
+
+        rows.push(row);
+    }
+
+    rows
+}
+
+fn block(top: bool, bottom: bool) -> char {
+    match (top, bottom) {
+        (false, false) => ' ',
+        (true, false) => '▀',
+        (false, true) => '▄',
+        (true, true) => '█',
+    }
+}
+
+// Braille dots are numbered down the left column, then the right, with the
+// bottom row last.
+fn braille<F: Fn(usize, usize) -> bool>(pixel: F) -> char {
+    const DOTS: [(usize, usize, u32); 8] = [
+        (0, 0, 0x01), (0, 1, 0x02), (0, 2, 0x04), (1, 0, 0x08),
+        (1, 1, 0x10), (1, 2, 0x20), (0, 3, 0x40), (1, 3, 0x80),
+    ];
+
+    let bits = DOTS
+        .iter()
+        .filter(|(x, y, _)| pixel(*x, *y))
+        .fold(0, |bits, (_, _, bit)| bits | bit);
+
+    char::from_u32(0x2800 + bits).unwrap()
+}
@@ -1,36 +1,22 @@
-use std::sync::mpsc;
-use std::thread;
-use std::time::Duration;
-
+// A 60 Hz countdown timer, ticked once per frame.
 pub struct Timer {
-    tx: mpsc::Sender<u8>,
-    rx: mpsc::Receiver<u8>,
-    last: u8,
+    value: u8,
 }
 
 impl Timer {
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::channel();
-        let last = 0;
-        Self { tx, rx, last }
+        Self { value: 0 }
     }
 
     pub fn set(&mut self, i: u8) {
-        let tx = self.tx.clone();
-        tx.send(i).unwrap();
-        thread::spawn(move || {
-            for j in (0 .. i).rev() {
-                thread::sleep(Duration::from_micros(16600));
-                tx.send(j).unwrap();
-            }
-        });
+        self.value = i;
     }
 
-    pub fn get(&mut self) -> u8 {
-        while let Ok(i) = self.rx.try_recv() {
-            self.last = i;
-        }
+    pub fn get(&self) -> u8 {
+        self.value
+    }
 
-        self.last
+    pub fn tick(&mut self) {
+        self.value = self.value.saturating_sub(1);
     }
 }
@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::decode;
+use crate::disasm::{self, Labels};
+use crate::expr;
+use crate::types::*;
+
+// Execution traces, one record per executed instruction.
+//
+// The text format has one line per instruction:
+//
+//   cycle pc opcode mnemonic changes
+//
+// where changes are the registers the instruction wrote, e.g. "V0=05 I=0300".
+// Traces limited to address ranges start with a FILTERED line naming them.
+//
+// The binary format starts with MAGIC, a version byte and a flags byte, with
+// FILTERED_FLAG set when limited to address ranges. Each record is the
+// cycle (u64), PC (u16) and opcode (u16), a change mask (u32, bit n for Vn
+// and bit 16 for I) and the new value of each changed register in mask
+// order. V registers take one byte and I two. Multi-byte fields are
+// little-endian.
+
+pub const MAGIC: &[u8; 4] = b"NNTR";
+pub const VERSION: u8 = 2;
+pub const I_BIT: u32 = 1 << 16;
+pub const FILTERED: &str = "# filtered to";
+pub const FILTERED_FLAG: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+// Machine state an instruction may change, captured around each step.
+#[derive(Clone, Copy)]
+pub struct Registers {
+    pub v: [Byte; 16],
+    pub i: Address,
+}
+
+pub struct Record {
+    pub cycle: u64,
+    pub pc: Address,
+    pub opcode: Instruction,
+    pub before: Registers,
+    pub after: Registers,
+}
+
+impl Record {
+    pub fn mask(&self) -> u32 {
+        let mut mask = 0;
+
+        for x in 0 .. 16 {
+            if self.before.v[x] != self.after.v[x] {
+                mask |= 1 << x;
+            }
+        }
+        if self.before.i != self.after.i {
+            mask |= I_BIT;
+        }
+
+        mask
+    }
+}
+
+pub struct Tracer {
+    out: BufWriter<File>,
+    format: Format,
+    ranges: Vec<(Address, Address)>,
+    labels: Labels,
+}
+
+impl Tracer {
+    pub fn new(path: &str, format: Format, ranges: Vec<(Address, Address)>) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        match format {
+            Format::Binary => {
+                out.write_all(MAGIC)?;
+                out.write_all(&[VERSION, if ranges.is_empty() { 0 } else { FILTERED_FLAG }])?;
+            },
+            Format::Text if !ranges.is_empty() => {
+                let ranges: Vec<String> = ranges.iter().map(|(start, end)| format!("{start:04x}-{end:04x}")).collect();
+                writeln!(out, "{FILTERED} {}", ranges.join(" "))?;
+            },
+            Format::Text => (),
+        }
+
+        Ok(Self { out, format, ranges, labels: Labels::new() })
+    }
+
+    pub fn set_labels(&mut self, labels: Labels) {
+        self.labels = labels;
+    }
+
+    pub fn trace(&mut self, record: &Record) -> io::Result<()> {
+        let pc = record.pc;
+        if !self.ranges.is_empty() && !self.ranges.iter().any(|(start, end)| (*start ..= *end).contains(&pc)) {
+            return Ok(());
+        }
+
+        match self.format {
+            Format::Text => self.write_text(record),
+            Format::Binary => self.write_binary(record),
+        }
+    }
+
+    fn write_text(&mut self, record: &Record) -> io::Result<()> {
+        let mnemonic = disasm::mnemonic(&decode::decode(record.opcode), &self.labels);
+        let mask = record.mask();
+        let mut changes = Vec::new();
+
+        for x in 0 .. 16 {
+            if mask & 1 << x != 0 {
+                changes.push(format!("V{x:X}={:02x}", record.after.v[x]));
+            }
+        }
+        if mask & I_BIT != 0 {
+            changes.push(format!("I={:04x}", record.after.i));
+        }
+
+        let line = format!(
+            "{:8} {:04x} {:04x} {:24} {}",
+            record.cycle,
+            record.pc,
+            record.opcode,
+            mnemonic,
+            changes.join(" "),
+        );
+
+        writeln!(self.out, "{}", line.trim_end())
+    }
+
+    fn write_binary(&mut self, record: &Record) -> io::Result<()> {
+        let mask = record.mask();
+
+        self.out.write_all(&record.cycle.to_le_bytes())?;
+        self.out.write_all(&record.pc.to_le_bytes())?;
+        self.out.write_all(&record.opcode.to_le_bytes())?;
+        self.out.write_all(&mask.to_le_bytes())?;
+
+        for x in 0 .. 16 {
+            if mask & 1 << x != 0 {
+                self.out.write_all(&[record.after.v[x]])?;
+            }
+        }
+        if mask & I_BIT != 0 {
+            self.out.write_all(&record.after.i.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+// Parses an address range such as 0x200-0x2ff.
+pub fn parse_range(s: &str) -> Result<(Address, Address), String> {
+    let (start, end) = s.split_once('-').ok_or_else(|| format!("bad address range: {s}"))?;
+    let start = expr::parse_number(start)? as Address;
+    let end = expr::parse_number(end)? as Address;
+
+    if start > end {
+        return Err(format!("bad address range: {s}"));
+    }
+
+    Ok((start, end))
+}
@@ -0,0 +1,457 @@
+use std::fs;
+
+use crate::decode;
+use crate::disasm::{self, Labels};
+use crate::trace::{self, FILTERED, FILTERED_FLAG, I_BIT, MAGIC};
+use crate::types::*;
+
+// Compares two per-instruction logs and reports the first divergence.
+//
+// Either log may be one of our traces, text or binary, or an external log
+// with one instruction per line given as key/value pairs, e.g.
+//
+//   PC:0200 OP:6005 V0:00 V1:00 ... VF:00 I:0000
+//
+// Keys are case-insensitive, separated from values by : or =, and values are
+// hex with an optional 0x or $ prefix. Registers missing from a line are not
+// compared. Lines with no PC key are read as "pc opcode ..." if they start
+// with two hex words. External registers are taken to be the state before
+// the instruction runs, unless post is set.
+//
+// Our traces limited with --trace-range leave out instructions, and with
+// them the registers those changed, so they cannot be diffed.
+
+const SEARCH_WINDOW: usize = 1000;
+
+#[derive(Clone)]
+struct Step {
+    line: usize,
+    pc: Address,
+    opcode: Option<Instruction>,
+    v: [Option<Byte>; 16],
+    i: Option<Address>,
+}
+
+impl Step {
+    fn new(line: usize, pc: Address) -> Self {
+        Self { line, pc, opcode: None, v: [None; 16], i: None }
+    }
+
+    fn describe(&self, labels: &Labels) -> String {
+        let mut s = format!("line {:6}: {:04x}", self.line, self.pc);
+
+        if let Some(opcode) = self.opcode {
+            s.push_str(&format!(" {opcode:04x}  {:20}", disasm::mnemonic(&decode::decode(opcode), labels)));
+        }
+        for (x, v) in self.v.iter().enumerate() {
+            if let Some(v) = v {
+                s.push_str(&format!(" V{x:X}={v:02x}"));
+            }
+        }
+        if let Some(i) = self.i {
+            s.push_str(&format!(" I={i:04x}"));
+        }
+
+        s
+    }
+
+    fn differences(&self, other: &Step) -> Vec<String> {
+        let mut diffs = Vec::new();
+
+        if self.pc != other.pc {
+            diffs.push(format!("PC {:04x} != {:04x}", self.pc, other.pc));
+        }
+        if let (Some(a), Some(b)) = (self.opcode, other.opcode) {
+            if a != b {
+                diffs.push(format!("opcode {a:04x} != {b:04x}"));
+            }
+        }
+        for x in 0 .. 16 {
+            if let (Some(a), Some(b)) = (self.v[x], other.v[x]) {
+                if a != b {
+                    diffs.push(format!("V{x:X} {a:02x} != {b:02x}"));
+                }
+            }
+        }
+        if let (Some(a), Some(b)) = (self.i, other.i) {
+            if a != b {
+                diffs.push(format!("I {a:04x} != {b:04x}"));
+            }
+        }
+
+        diffs
+    }
+}
+
+pub fn diff(ours: &str, theirs: &str, context: usize, post: bool) -> Result<(), String> {
+    let a = read(ours, false)?;
+    let b = read(theirs, post)?;
+
+    let (start_a, start_b) = align(&a, &b).ok_or("no common instruction found to align the logs")?;
+    let labels = Labels::new();
+
+    if start_a > 0 || start_b > 0 {
+        println!("Aligned {ours} step {start_a} with {theirs} step {start_b}");
+    }
+
+    let a = &a[start_a ..];
+    let b = &b[start_b ..];
+    let len = usize::min(a.len(), b.len());
+
+    for n in 0 .. len {
+        let diffs = a[n].differences(&b[n]);
+        if diffs.is_empty() {
+            continue;
+        }
+
+        println!("First divergence at step {n}: {}", diffs.join(", "));
+        let width = usize::max(ours.len(), theirs.len());
+        for k in n.saturating_sub(context) ..= usize::min(n + context, len - 1) {
+            let mark = if k == n { ">" } else { " " };
+            println!("{mark} {ours:width$}  {}", a[k].describe(&labels));
+            println!("{mark} {theirs:width$}  {}", b[k].describe(&labels));
+        }
+        return Ok(());
+    }
+
+    if a.len() != b.len() {
+        let (short, long) = if a.len() < b.len() { (ours, theirs) } else { (theirs, ours) };
+        println!("No divergence in {len} steps, but {short} ends before {long}");
+    } else {
+        println!("No divergence in {len} steps");
+    }
+
+    Ok(())
+}
+
+// Finds the earliest pair of steps with the same PC and opcode.
+fn align(a: &[Step], b: &[Step]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+
+    for (i, x) in a.iter().enumerate().take(SEARCH_WINDOW) {
+        if best.is_some_and(|(bi, bj)| i >= bi + bj) {
+            break;
+        }
+        let found = b.iter().take(SEARCH_WINDOW).position(|y| {
+            x.pc == y.pc && (x.opcode.is_none() || y.opcode.is_none() || x.opcode == y.opcode)
+        });
+        if let Some(j) = found {
+            if best.is_none_or(|(bi, bj)| i + j < bi + bj) {
+                best = Some((i, j));
+            }
+        }
+    }
+
+    best
+}
+
+fn read(path: &str, post: bool) -> Result<Vec<Step>, String> {
+    let bytes = fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+
+    if bytes.starts_with(MAGIC) {
+        return read_binary(&bytes).map_err(|err| format!("{path}: {err}"));
+    }
+
+    let text = String::from_utf8_lossy(&bytes);
+    if text.starts_with(FILTERED) {
+        return Err(format!("{path}: {}", filtered_error()));
+    }
+    let ours = text.lines().find(|line| !line.trim().is_empty()).is_some_and(is_our_text);
+
+    let steps = if ours { read_text(&text) } else { read_external(&text) };
+    let steps = steps.map_err(|err| format!("{path}: {err}"))?;
+
+    Ok(if post { to_pre_state(steps) } else { steps })
+}
+
+fn filtered_error() -> String {
+    "trace is limited to address ranges; diff a trace recorded without --trace-range".to_string()
+}
+
+// Our text lines are "cycle pc opcode mnemonic changes".
+fn is_our_text(line: &str) -> bool {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+
+    tokens.len() >= 3
+        && tokens[0].chars().all(|c| c.is_ascii_digit())
+        && is_hex_word(tokens[1])
+        && is_hex_word(tokens[2])
+}
+
+fn is_hex_word(s: &str) -> bool {
+    s.len() == 4 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn parse_hex(s: &str) -> Result<u32, String> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+
+    u32::from_str_radix(hex, 16).map_err(|_| format!("bad hex value: {s}"))
+}
+
+// Our traces record what each instruction changed. Replaying the changes
+// from the initial (zeroed) registers gives the state before each step.
+struct Replay {
+    v: [Byte; 16],
+    i: Address,
+}
+
+impl Replay {
+    fn step(&self, line: usize, pc: Address, opcode: Instruction) -> Step {
+        let mut step = Step::new(line, pc);
+        step.opcode = Some(opcode);
+        step.v = self.v.map(Some);
+        step.i = Some(self.i);
+        step
+    }
+}
+
+fn read_text(text: &str) -> Result<Vec<Step>, String> {
+    let mut replay = Replay { v: [0; 16], i: 0 };
+    let mut steps = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 3 {
+            continue;
+        }
+
+        let pc = parse_hex(tokens[1])? as Address;
+        let opcode = parse_hex(tokens[2])? as Instruction;
+        steps.push(replay.step(n + 1, pc, opcode));
+
+        for change in tokens.iter().filter_map(|t| t.split_once('=')) {
+            match change {
+                ("I", value) => replay.i = parse_hex(value)? as Address,
+                (reg, value) => {
+                    let x = parse_hex(reg.trim_start_matches('V'))? as usize;
+                    replay.v[x & 0xF] = parse_hex(value)? as Byte;
+                },
+            }
+        }
+    }
+
+    Ok(steps)
+}
+
+fn read_binary(bytes: &[u8]) -> Result<Vec<Step>, String> {
+    let mut replay = Replay { v: [0; 16], i: 0 };
+    let mut steps = Vec::new();
+    let mut pos = MAGIC.len();
+
+    let version = match bytes.get(pos) {
+        Some(version @ 1 ..= trace::VERSION) => *version,
+        _ => return Err("unsupported trace version".to_string()),
+    };
+    pos += 1;
+
+    if version >= 2 {
+        let flags = *bytes.get(pos).ok_or("truncated trace")?;
+        if flags & FILTERED_FLAG != 0 {
+            return Err(filtered_error());
+        }
+        pos += 1;
+    }
+
+    let mut take = |n: usize| -> Result<&[u8], String> {
+        let field = bytes.get(pos .. pos + n).ok_or("truncated trace")?;
+        pos += n;
+        Ok(field)
+    };
+
+    while let Ok(header) = take(16) {
+        let pc = Address::from_le_bytes([header[8], header[9]]);
+        let opcode = Instruction::from_le_bytes([header[10], header[11]]);
+        let mask = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+
+        steps.push(replay.step(steps.len() + 1, pc, opcode));
+
+        for x in 0 .. 16 {
+            if mask & 1 << x != 0 {
+                replay.v[x] = take(1)?[0];
+            }
+        }
+        if mask & I_BIT != 0 {
+            let i = take(2)?;
+            replay.i = Address::from_le_bytes([i[0], i[1]]);
+        }
+    }
+
+    Ok(steps)
+}
+
+fn read_external(text: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let tokens: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '|')
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let mut pc = None;
+        let mut step = Step::new(n + 1, 0);
+
+        for token in tokens.iter() {
+            let (key, value) = match token.split_once([':', '=']) {
+                Some((key, value)) if !value.is_empty() => (key.to_lowercase(), value),
+                _ => continue,
+            };
+            let value = match parse_hex(value) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            match key.as_str() {
+                "pc" => pc = Some(value as Address),
+                "op" | "opcode" | "instr" | "ins" => step.opcode = Some(value as Instruction),
+                "i" => step.i = Some(value as Address),
+                key => match key.strip_prefix('v').map(|x| usize::from_str_radix(x, 16)) {
+                    Some(Ok(x)) if x < 16 => step.v[x] = Some(value as Byte),
+                    _ => (),
+                },
+            }
+        }
+
+        if pc.is_none() {
+            if let [a, b, ..] = tokens[..] {
+                let a = a.trim_end_matches(':');
+                if is_hex_word(a) && is_hex_word(b) {
+                    pc = Some(parse_hex(a)? as Address);
+                    step.opcode = Some(parse_hex(b)? as Instruction);
+                }
+            }
+        }
+
+        if let Some(pc) = pc {
+            step.pc = pc;
+            steps.push(step);
+        }
+    }
+
+    Ok(steps)
+}
+
+// Shifts registers logged after each instruction onto the next step, so that
+// every step holds the state before it runs.
+fn to_pre_state(steps: Vec<Step>) -> Vec<Step> {
+    let mut shifted = steps.clone();
+
+    for (n, step) in shifted.iter_mut().enumerate() {
+        match n.checked_sub(1).map(|prev| &steps[prev]) {
+            Some(prev) => {
+                step.v = prev.v;
+                step.i = prev.i;
+            },
+            None => {
+                step.v = [Some(0); 16];
+                step.i = Some(0);
+            },
+        }
+    }
+
+    shifted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::{Format, Record, Registers, Tracer};
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("nn-trace-diff-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    // Traces LD V0, 5; LD I, 0x300; ADD V0, 1 with the tracer, and reads the
+    // trace back.
+    fn write_and_read(name: &str, format: Format, ranges: Vec<(Address, Address)>) -> Result<Vec<Step>, String> {
+        let path = temp_path(name);
+        let mut tracer = Tracer::new(&path, format, ranges).unwrap();
+        let mut registers = Registers { v: [0; 16], i: 0 };
+
+        for (cycle, (pc, opcode)) in [(0x200, 0x6005), (0x202, 0xA300), (0x204, 0x7001)].into_iter().enumerate() {
+            let before = registers;
+            match opcode {
+                0x6005 => registers.v[0] = 5,
+                0xA300 => registers.i = 0x300,
+                _ => registers.v[0] += 1,
+            }
+            let record = Record { cycle: cycle as u64, pc, opcode, before, after: registers };
+            tracer.trace(&record).unwrap();
+        }
+        drop(tracer);
+
+        let steps = read(&path, false);
+        std::fs::remove_file(&path).unwrap();
+        steps
+    }
+
+    fn check_steps(steps: &[Step]) {
+        assert_eq!(steps.iter().map(|step| (step.pc, step.opcode)).collect::<Vec<_>>(), [
+            (0x200, Some(0x6005)), (0x202, Some(0xA300)), (0x204, Some(0x7001)),
+        ]);
+        assert_eq!(steps.iter().map(|step| (step.v[0], step.i)).collect::<Vec<_>>(), [
+            (Some(0), Some(0)), (Some(5), Some(0)), (Some(5), Some(0x300)),
+        ]);
+        assert!(steps.iter().all(|step| step.v[1 ..].iter().all(|v| *v == Some(0))));
+    }
+
+    #[test]
+    fn text_traces() {
+        check_steps(&write_and_read("text", Format::Text, Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn binary_traces() {
+        check_steps(&write_and_read("binary", Format::Binary, Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn filtered_traces_are_refused() {
+        for (name, format) in [("filtered-text", Format::Text), ("filtered-binary", Format::Binary)] {
+            let err = write_and_read(name, format, vec![(0x202, 0x2ff)]).err().unwrap();
+            assert!(err.contains("--trace-range"), "{err}");
+        }
+    }
+
+    #[test]
+    fn version_1_binary_traces() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&[0x00, 0x02, 0x05, 0x60, 0x01, 0x00, 0x00, 0x00, 0x05]);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&[0x02, 0x02, 0x01, 0x70, 0x01, 0x00, 0x00, 0x00, 0x06]);
+
+        let steps = read_binary(&bytes).unwrap();
+        assert_eq!(steps.iter().map(|step| (step.pc, step.v[0])).collect::<Vec<_>>(), [(0x200, Some(0)), (0x202, Some(5))]);
+
+        bytes.pop();
+        assert_eq!(read_binary(&bytes).err(), Some("truncated trace".to_string()));
+        bytes[MAGIC.len()] = 9;
+        assert_eq!(read_binary(&bytes).err(), Some("unsupported trace version".to_string()));
+    }
+
+    #[test]
+    fn external_logs() {
+        let text = "PC:0200 OP:6005 V0:00 I:0000\n\
+                    pc=0x202, op=$a300, v0=05\n\
+                    ignored line\n\
+                    0204: 7001 whatever\n";
+        let steps = read_external(text).unwrap();
+
+        assert_eq!(steps.iter().map(|step| (step.line, step.pc, step.opcode)).collect::<Vec<_>>(), [
+            (1, 0x200, Some(0x6005)), (2, 0x202, Some(0xA300)), (4, 0x204, Some(0x7001)),
+        ]);
+        assert_eq!(steps[1].v[0], Some(5));
+        assert_eq!(steps[1].v[1], None);
+        assert_eq!(steps[2].i, None);
+
+        let steps = to_pre_state(steps);
+        assert_eq!(steps.iter().map(|step| step.v[0]).collect::<Vec<_>>(), [Some(0), Some(0), Some(5)]);
+    }
+}